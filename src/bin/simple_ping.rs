@@ -7,7 +7,9 @@ use futures::future::join_all;
 use futures::{FutureExt};
 use ping_rs::*;
 
-const PING_OPTS: PingOptions = PingOptions { ttl: 128, dont_fragment: true };
+const PING_OPTS: PingOptions = PingOptions { ttl: 128, dont_fragment: true, tos: 0, flow_label: 0, source: None, interface: None,
+                                            mark: None, priority: None, dont_route: false, netns: None,
+                                            socket_type: SocketType::Auto, kernel_timestamps: false };
 
 fn main() {
     let addrs = ["172.67.172.103", "8.8.8.8", "209.17.116.106", "209.17.116.160", "::1"]
@@ -16,6 +18,7 @@ fn main() {
 
     sync_ping(&addrs, &data);
     async_ping(&addrs, Arc::new(&data));
+    session_ping(&addrs[addrs.len()-1], &data);
 
     println!("Done.");
 }
@@ -23,13 +26,26 @@ fn main() {
 const TIMEOUT: Duration = Duration::from_secs(5);
 fn sync_ping(addrs: &[IpAddr], data: &[u8]) {
     println!("Sync ping 5 times");
-    for i in 0..addrs.len() {
-        let result = send_ping(&addrs[i], TIMEOUT, &data, Some(&PING_OPTS));
+    for (i, addr) in addrs.iter().enumerate() {
+        let result = send_ping(addr, TIMEOUT, data, Some(&PING_OPTS));
 
         println!("{} > Result = {:?}", i+1, result);
     }
 }
 
+fn session_ping(addr: &IpAddr, data: &[u8]) {
+    println!("Ping {addr} 5 times with a single session");
+    let mut pinger = Pinger::new(addr, Some(&PING_OPTS)).unwrap();
+    for i in 0..5 {
+        match pinger.ping(TIMEOUT, data) {
+            Ok(reply) => println!("{} > {} bytes from {}: icmp_seq={} ttl={} time={:?}", i+1, reply.size, reply.address,
+                                  reply.sequence.map_or("?".into(), |v| v.to_string()), reply.ttl.map_or("?".into(), |v| v.to_string()), reply.rtt),
+            Err(e) => println!("{} > Error = {:?}", i+1, e)
+        }
+    }
+}
+
+#[allow(clippy::redundant_allocation)]
 fn async_ping(addrs: &[IpAddr], data: Arc<&[u8]>) {
     println!("Async ping 5 times");
 
@@ -0,0 +1,114 @@
+//! Ping by host name, resolved with the system resolver.
+
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use futures::channel::oneshot;
+use crate::{PingError, PingOptions, PingReply, Pinger, Result, ping_mod};
+
+/// Address family to ping a host name with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressFamily {
+    /// All addresses, in the order of the resolver.
+    #[default]
+    Any,
+    /// IPv4 addresses first, then IPv6 ones.
+    PreferIpv4,
+    /// IPv6 addresses first, then IPv4 ones.
+    PreferIpv6,
+    /// IPv4 addresses only.
+    Ipv4,
+    /// IPv6 addresses only.
+    Ipv6,
+}
+
+/// Reply of a ping to a host name.
+#[derive(Debug, Clone)]
+pub struct HostReply {
+    /// Resolved address that was pinged
+    pub destination: IpAddr,
+    /// Reply of `destination`
+    pub reply: PingReply,
+}
+
+/// Resolve `host` and ping its addresses of `family` in turn, until one of them replies. Each address is given the whole
+/// `timeout`. When none replies, the error of the last one is returned.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use ping_rs::AddressFamily;
+///
+/// match ping_rs::send_ping_host("localhost", AddressFamily::PreferIpv4, Duration::from_secs(1), &[1,2,3,4], None) {
+///     Ok(r) => println!("Reply from {} ({:?}): time={:?}", r.destination, ping_rs::lookup_host_name(&r.reply.address).ok(), r.reply.rtt),
+///     Err(e) => println!("{e}")
+/// }
+/// ```
+pub fn send_ping_host(host: &str, family: AddressFamily, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> Result<HostReply> {
+    let mut result = Err(PingError::HostNotFound(host.to_string()));
+    for destination in resolve(host, family)? {
+        result = Pinger::new(&destination, options).and_then(|mut pinger| pinger.ping(timeout, data))
+            .map(|reply| HostReply { destination, reply });
+        if result.is_ok() { break; }
+    }
+    result
+}
+
+/// Same as [`send_ping_host`], but asynchronous. The name is resolved on a thread of its own.
+#[allow(clippy::redundant_allocation)]
+pub async fn send_ping_host_async(host: &str, family: AddressFamily, timeout: Duration, data: Arc<&[u8]>, options: Option<&PingOptions>)
+                                  -> Result<HostReply> {
+    let (sender, receiver) = oneshot::channel();
+    let name = host.to_string();
+    thread::spawn(move || { let _ = sender.send(resolve(&name, family)); });
+    let destinations = receiver.await.unwrap_or(Err(PingError::HostNotFound(host.to_string())))?;
+
+    let mut result = Err(PingError::HostNotFound(host.to_string()));
+    for destination in destinations {
+        result = match Pinger::new(&destination, options) {
+            Ok(mut pinger) => pinger.ping_async(timeout, data.clone()).await,
+            Err(e) => Err(e)
+        }.map(|reply| HostReply { destination, reply });
+        if result.is_ok() { break; }
+    }
+    result
+}
+
+/// Name of `addr` by a reverse lookup of the system resolver, e.g. to display the replying address.
+pub fn lookup_host_name(addr: &IpAddr) -> io::Result<String> {
+    ping_mod::lookup_host_name(addr)
+}
+
+// INTERNAL
+
+/// Addresses of `host` to ping, in order.
+fn resolve(host: &str, family: AddressFamily) -> Result<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = Vec::new();
+    for addr in (host, 0).to_socket_addrs().map_err(|_| PingError::HostNotFound(host.to_string()))? {
+        if !addresses.contains(&addr.ip()) { addresses.push(addr.ip()); }
+    }
+    match family {
+        AddressFamily::Any => (),
+        AddressFamily::PreferIpv4 => addresses.sort_by_key(|a| a.is_ipv6()),
+        AddressFamily::PreferIpv6 => addresses.sort_by_key(|a| a.is_ipv4()),
+        AddressFamily::Ipv4 => addresses.retain(|a| a.is_ipv4()),
+        AddressFamily::Ipv6 => addresses.retain(|a| a.is_ipv6()),
+    }
+    if addresses.is_empty() { Err(PingError::HostNotFound(host.to_string())) } else { Ok(addresses) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use crate::PingError;
+    use crate::host::{AddressFamily, resolve};
+
+    #[test]
+    fn resolve_keeps_addresses_of_family() {
+        assert_eq!(resolve("::1", AddressFamily::PreferIpv4).unwrap(), vec![IpAddr::from(Ipv6Addr::LOCALHOST)]);
+        assert_eq!(resolve("127.0.0.1", AddressFamily::Ipv4).unwrap(), vec![IpAddr::from(Ipv4Addr::LOCALHOST)]);
+        assert!(matches!(resolve("127.0.0.1", AddressFamily::Ipv6), Err(PingError::HostNotFound(_))));
+        assert!(matches!(resolve("", AddressFamily::Any), Err(PingError::HostNotFound(host)) if host.is_empty()));
+    }
+}
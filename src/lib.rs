@@ -1,7 +1,14 @@
 //! Provide ICMP Echo (ping) functionality for both Windows and Linux. This library does not need root/admin privilege for pinging.
-//! It provides sync and async ping functions: [`send_ping`] and [`send_ping_async`].
+//! It provides sync and async ping functions: [`send_ping`] and [`send_ping_async`]. When the same host is pinged repeatedly,
+//! a [`Pinger`] session keeps its socket open and numbers each probe with its own sequence. On Linux, [`MultiPinger`] pings
+//! many hosts concurrently over a single socket per address family.
 //!
-//! Linux version still does not support "Do not Fragment" flag yet.
+//! # Cargo features
+//!
+//! * `tokio` - On Linux, adds [`send_ping_tokio`] and `Pinger::ping_tokio`, which wait for the reply on the Tokio runtime's
+//!   own reactor and timer instead of the crate's background thread.
+//! * `async-io` - On Linux, adds [`send_ping_async_io`] and `Pinger::ping_async_io`, which do the same with the `async-io`
+//!   reactor used by `smol` and `async-std`.
 //!
 //! # Usage Example
 //!
@@ -16,10 +23,10 @@
 //!     let addr = "8.8.8.8".parse().unwrap();
 //!     let data = [1,2,3,4];  // ping data
 //!     let timeout = Duration::from_secs(1);
-//!     let options = ping_rs::PingOptions { ttl: 128, dont_fragment: true };
+//!     let options = ping_rs::PingOptions { ttl: 128, dont_fragment: true, ..Default::default() };
 //!     let result = ping_rs::send_ping(&addr, timeout, &data, Some(&options));
 //!     match result {
-//!         Ok(reply) => println!("Reply from {}: bytes={} time={}ms TTL={}", reply.address, data.len(), reply.rtt, options.ttl),
+//!         Ok(reply) => println!("Reply from {}: bytes={} time={:?} TTL={:?}", reply.address, reply.data.len(), reply.rtt, reply.ttl),
 //!         Err(e) => println!("{:?}", e)
 //!     }
 //! }
@@ -39,11 +46,11 @@
 //!     let data = [1,2,3,4];  // ping data
 //!     let data_arc = Arc::new(&data[..]);
 //!     let timeout = Duration::from_secs(1);
-//!     let options = ping_rs::PingOptions { ttl: 128, dont_fragment: true };
+//!     let options = ping_rs::PingOptions { ttl: 128, dont_fragment: true, ..Default::default() };
 //!     let future = ping_rs::send_ping_async(&addr, timeout, data_arc, Some(&options));
 //!     let result = futures::executor::block_on(future);
 //!     match result {
-//!         Ok(reply) => println!("Reply from {}: bytes={} time={}ms TTL={}", reply.address, data.len(), reply.rtt, options.ttl),
+//!         Ok(reply) => println!("Reply from {}: bytes={} time={:?} TTL={:?}", reply.address, reply.data.len(), reply.rtt, reply.ttl),
 //!         Err(e) => println!("{:?}", e)
 //!     }
 //! }
@@ -51,50 +58,119 @@
 
 mod windows_ping;
 mod linux_ping;
+mod host;
+mod traceroute;
+mod monitor;
+mod path_mtu;
+mod periodic;
 
-use std::io;
-use std::net::IpAddr;
+use std::{error, fmt, io};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
-
-/// Contains constant values represent general errors.
-#[allow(non_snake_case)]
-pub mod IpStatus {
-    #![allow(non_upper_case_globals)]
-
-    pub type Type = u32;
-    pub const Success: Type = 0;
-    //BufferTooSmall = 11000 + 1;
-
-    pub const DestinationNetworkUnreachable: Type = 11000 + 2;
-    pub const DestinationHostUnreachable: Type = 11000 + 3;
-    pub const DestinationProtocolUnreachable: Type = 11000 + 4;
-    pub const DestinationPortUnreachable: Type = 11000 + 5;
-    pub const DestinationProhibited: Type = 11000 + 19;
-
-    pub const NoResources: Type = 11000 + 6;
-    pub const BadOption: Type = 11000 + 7;
-    pub const HardwareError: Type = 11000 + 8;
-    pub const PacketTooBig: Type = 11000 + 9;
-    pub const TimedOut: Type = 11000 + 10;
-    pub const BadRoute: Type = 11000 + 12;
-
-    pub const TtlExpired: Type = 11000 + 13;
-    pub const TtlReassemblyTimeExceeded: Type = 11000 + 14;
-
-    pub const ParameterProblem: Type = 11000 + 15;
-    pub const SourceQuench: Type = 11000 + 16;
-    pub const BadDestination: Type = 11000 + 18;
-
-    pub const DestinationUnreachable: Type = 11000 + 40;
-    pub const TimeExceeded: Type = 11000 + 41;
-    pub const BadHeader: Type = 11000 + 42;
-    pub const UnrecognizedNextHeader: Type = 11000 + 43;
-    pub const IcmpError: Type = 11000 + 44;
-    pub const DestinationScopeMismatch: Type = 11000 + 45;
+use std::time::{Duration, SystemTime};
+
+macro_rules! ip_status {
+    ($($name:ident = $value:expr => $message:literal,)*) => {
+        /// Status of an ICMP request. The values are the ones of Windows' `IP_STATUS`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u32)]
+        pub enum IpStatus {
+            $(#[doc = $message] $name = $value,)*
+        }
+
+        impl IpStatus {
+            /// Human-readable description of the status.
+            pub fn message(self) -> &'static str {
+                match self { $(IpStatus::$name => $message,)* }
+            }
+        }
+
+        impl TryFrom<u32> for IpStatus {
+            type Error = u32;
+
+            /// Fails with `value` when it is not a known status.
+            fn try_from(value: u32) -> std::result::Result<IpStatus, u32> {
+                $(if value == $value { return Ok(IpStatus::$name); })*
+                Err(value)
+            }
+        }
+    };
+}
+
+ip_status! {
+    Success = 0 => "Success",
+    BufferTooSmall = 11000 + 1 => "Reply buffer too small",
+
+    DestinationNetworkUnreachable = 11000 + 2 => "Destination network unreachable",
+    DestinationHostUnreachable = 11000 + 3 => "Destination host unreachable",
+    DestinationProtocolUnreachable = 11000 + 4 => "Destination protocol unreachable",
+    DestinationPortUnreachable = 11000 + 5 => "Destination port unreachable",
+    DestinationProhibited = 11000 + 19 => "Communication with the destination administratively prohibited",
+
+    NoResources = 11000 + 6 => "Insufficient resources",
+    BadOption = 11000 + 7 => "Bad IP option",
+    HardwareError = 11000 + 8 => "Hardware error",
+    PacketTooBig = 11000 + 9 => "Packet too big",
+    TimedOut = 11000 + 10 => "Request timed out",
+    BadRequest = 11000 + 11 => "Bad request",
+    BadRoute = 11000 + 12 => "Bad route",
+
+    TtlExpired = 11000 + 13 => "TTL expired in transit",
+    TtlReassemblyTimeExceeded = 11000 + 14 => "TTL expired during fragment reassembly",
+
+    ParameterProblem = 11000 + 15 => "Parameter problem",
+    SourceQuench = 11000 + 16 => "Source quench",
+    OptionTooBig = 11000 + 17 => "IP option too big",
+    BadDestination = 11000 + 18 => "Bad destination",
+
+    DestinationUnreachable = 11000 + 40 => "Destination unreachable",
+    TimeExceeded = 11000 + 41 => "Time exceeded",
+    BadHeader = 11000 + 42 => "Bad IP header",
+    UnrecognizedNextHeader = 11000 + 43 => "Unrecognized next header",
+    IcmpError = 11000 + 44 => "ICMP error",
+    DestinationScopeMismatch = 11000 + 45 => "Destination scope mismatch",
 
     // for example, no network interfaces are suitable to route the ping package.
-    pub const GeneralFailure: Type = 11000 + 50;
+    GeneralFailure = 11000 + 50 => "General failure",
+}
+
+impl IpStatus {
+    /// Whether the same request may succeed when retried later, such as after a time out or a congested link. Errors that
+    /// are not transient are [permanent](IpStatus::is_permanent).
+    pub fn is_transient(self) -> bool {
+        matches!(self, IpStatus::NoResources | IpStatus::TimedOut | IpStatus::DestinationHostUnreachable
+                     | IpStatus::TtlReassemblyTimeExceeded | IpStatus::SourceQuench)
+    }
+
+    /// Whether the request fails again until the network, the destination or the request changes.
+    pub fn is_permanent(self) -> bool {
+        self != IpStatus::Success && !self.is_transient()
+    }
+
+    /// Whether the status is reported by a router or the destination with an ICMP error message.
+    pub fn is_remote(self) -> bool {
+        matches!(self, IpStatus::DestinationNetworkUnreachable | IpStatus::DestinationHostUnreachable
+                     | IpStatus::DestinationProtocolUnreachable | IpStatus::DestinationPortUnreachable | IpStatus::DestinationProhibited
+                     | IpStatus::PacketTooBig | IpStatus::BadRoute | IpStatus::TtlExpired | IpStatus::TtlReassemblyTimeExceeded
+                     | IpStatus::ParameterProblem | IpStatus::SourceQuench | IpStatus::DestinationUnreachable | IpStatus::TimeExceeded
+                     | IpStatus::BadHeader | IpStatus::UnrecognizedNextHeader | IpStatus::IcmpError
+                     | IpStatus::DestinationScopeMismatch)
+    }
+
+    /// Whether the failure is detected by this host, without any ICMP error message. Some statuses are neither local nor
+    /// [remote](IpStatus::is_remote), such as [`IpStatus::TimedOut`], where no answer came at all, or
+    /// [`IpStatus::BadRequest`].
+    pub fn is_local(self) -> bool {
+        matches!(self, IpStatus::BufferTooSmall | IpStatus::NoResources | IpStatus::BadOption | IpStatus::HardwareError
+                     | IpStatus::OptionTooBig | IpStatus::BadDestination | IpStatus::GeneralFailure)
+    }
+}
+
+impl fmt::Display for IpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -102,18 +178,120 @@ pub struct PingOptions {
     /// Package TTL
     pub ttl: u8,
 
-    /// Socket's Dont Fragment
-    pub dont_fragment: bool
+    /// Set "Don't Fragment" on the request. A request bigger than the path MTU then fails with [`IpStatus::PacketTooBig`].
+    pub dont_fragment: bool,
+
+    /// IPv4 type of service, or IPv6 traffic class: the DSCP in the upper 6 bits and the ECN in the lower 2. Windows does
+    /// not apply it to IPv6.
+    pub tos: u8,
+
+    /// IPv6 flow label, up to 20 bits. Zero lets the kernel choose one. Windows ignores it.
+    pub flow_label: u32,
+
+    /// Local address to send from, of the same family as the destination. By default, the kernel picks the one of the route.
+    pub source: Option<IpAddr>,
+
+    /// Network interface to send through (`SO_BINDTODEVICE`), which may also be a VRF device. Windows ignores it.
+    pub interface: Option<Interface>,
+
+    /// Firewall mark of the requests (`SO_MARK`), for policy routing and filtering. Needs `CAP_NET_ADMIN`. Windows ignores it.
+    pub mark: Option<u32>,
+
+    /// Queueing priority of the requests (`SO_PRIORITY`). Priorities above 6 need `CAP_NET_ADMIN`. Windows ignores it.
+    pub priority: Option<u32>,
+
+    /// Only reach hosts on the directly connected networks, ignoring gateways (`SO_DONTROUTE`). Linux applies it to IPv4 only,
+    /// and Windows ignores it.
+    pub dont_route: bool,
+
+    /// Network namespace to ping from, such as `/var/run/netns/tenant-a`. The socket is created in it, on a thread of its
+    /// own, and keeps using it afterwards. Needs `CAP_SYS_ADMIN`. Windows ignores it.
+    pub netns: Option<PathBuf>,
+
+    /// Kind of ICMP socket to ping with. Windows ignores it.
+    pub socket_type: SocketType,
+
+    /// Take the send and receive times from kernel software timestamps (`SO_TIMESTAMPING`), so the RTT leaves out the time
+    /// this process takes to send the request and to wake up for the reply. Windows ignores it.
+    pub kernel_timestamps: bool,
 }
 
-/// Ping reply contains the destination address (from ICMP reply) and Round-Trip Time
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions {
+            ttl: 128,
+            dont_fragment: false,
+            tos: 0,
+            flow_label: 0,
+            source: None,
+            interface: None,
+            mark: None,
+            priority: None,
+            dont_route: false,
+            netns: None,
+            socket_type: SocketType::Auto,
+            kernel_timestamps: false,
+        }
+    }
+}
+
+/// ICMP socket used on Linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SocketType {
+    /// Datagram socket, or raw socket when the process group is outside `net.ipv4.ping_group_range`.
+    #[default]
+    Auto,
+    /// Unprivileged ICMP datagram socket (`SOCK_DGRAM`). The process group must be in `net.ipv4.ping_group_range`.
+    Datagram,
+    /// Raw socket (`SOCK_RAW`). Needs `CAP_NET_RAW`.
+    Raw,
+}
+
+/// Network interface of [`PingOptions::interface`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Interface {
+    /// Interface name, such as `eth0`
+    Name(String),
+    /// Interface index
+    Index(u32),
+}
+
+/// Ping reply contains the destination address (from ICMP reply), Round-Trip Time and what the reply carried.
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct PingReply {
     /// Destination address from ICMP reply
     pub address: IpAddr,
-    /// Round-Trip Time in milliseconds
-    pub rtt: u32,
+    /// Scope of [`PingReply::address`] when it is a scoped IPv6 address, such as a link-local one: the index of the interface
+    /// the reply came from.
+    pub scope_id: Option<u32>,
+    /// Local address the reply was sent to, i.e. the source of the request. Windows does not report it.
+    pub source: Option<IpAddr>,
+    /// Round-Trip Time
+    pub rtt: Duration,
+    /// TTL (IPv4) or hop limit (IPv6) of the reply. Windows does not report it for IPv6.
+    pub ttl: Option<u8>,
+    /// Type of service (IPv4) or traffic class (IPv6) of the reply. Windows does not report it for IPv6.
+    pub tos: Option<u8>,
+    /// ICMP sequence number. Windows does not report it.
+    pub sequence: Option<u16>,
+    /// ICMP identifier. Windows does not report it.
+    pub ident: Option<u16>,
+    /// Size of the ICMP reply in bytes, header included
+    pub size: usize,
+    /// Echoed data
+    pub data: Vec<u8>,
+    /// When the request was sent
+    pub sent_at: SystemTime,
+    /// When the reply was received
+    pub received_at: SystemTime,
+}
+
+impl PingReply {
+    /// Round-Trip Time in whole milliseconds
+    pub fn rtt_ms(&self) -> u32 {
+        self.rtt.as_millis() as u32
+    }
 }
 
 /// Ping errors
@@ -123,10 +301,10 @@ pub enum PingError {
     BadParameter(&'static str),
 
     /// Unspecific OS errors
-    OsError(u32, String),
+    OsError(Arc<io::Error>),
 
     /// General Ping errors
-    IpError(IpStatus::Type),
+    IpError(IpStatus),
 
     /// Ping timed out
     TimedOut,
@@ -134,14 +312,48 @@ pub enum PingError {
     /// I/O async pending
     IoPending,
 
+    /// The process lacks the capability to apply the named option, such as `CAP_NET_ADMIN` for `mark`.
+    PermissionDenied(&'static str),
+
+    /// The host name resolves to no address of the requested family.
+    HostNotFound(String),
+
     /// size of data buffer for ping is too big. The first parameter is the maximum allowed size.
     DataSizeTooBig(usize),
+
+    /// The reply came back with only the beginning of the ping data. The parameter is the size of the data received.
+    TruncatedReply(usize),
 }
 
 impl From<io::Error> for PingError {
     fn from(value: io::Error) -> Self {
         if value.kind() == io::ErrorKind::WouldBlock { PingError::IoPending }
-        else { PingError::OsError(value.raw_os_error().unwrap_or(-1) as u32, value.to_string()) }
+        else { PingError::OsError(Arc::new(value)) }
+    }
+}
+
+impl fmt::Display for PingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PingError::BadParameter(name) => write!(f, "bad parameter: {name}"),
+            PingError::OsError(e) => write!(f, "OS error: {e}"),
+            PingError::IpError(status) => status.fmt(f),
+            PingError::TimedOut => f.write_str("ping timed out"),
+            PingError::IoPending => f.write_str("I/O pending"),
+            PingError::PermissionDenied(name) => write!(f, "not permitted to set {name}"),
+            PingError::HostNotFound(host) => write!(f, "no address found for {host}"),
+            PingError::DataSizeTooBig(max) => write!(f, "ping data bigger than {max} bytes"),
+            PingError::TruncatedReply(size) => write!(f, "reply truncated to {size} bytes of data"),
+        }
+    }
+}
+
+impl error::Error for PingError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PingError::OsError(e) => Some(e.as_ref()),
+            _ => None
+        }
     }
 }
 
@@ -154,15 +366,175 @@ use windows_ping as ping_mod;
 #[cfg(unix)]
 use linux_ping as ping_mod;
 
+#[cfg(unix)]
+pub use linux_ping::multi_pinger::MultiPinger;
+
+pub use host::{AddressFamily, HostReply, lookup_host_name, send_ping_host, send_ping_host_async};
+pub use traceroute::{Hop, HopProbe, TraceOptions, traceroute, traceroute_async};
+pub use monitor::{HopStats, PathMonitor};
+pub use path_mtu::{discover_path_mtu, discover_path_mtu_async};
+pub use periodic::{PeriodicReply, PingIter, Schedule, StopHandle, ping_iter, ping_stream};
+
 /// Send ICMP Echo package (ping) to the given address.
 #[inline(always)]
 pub fn send_ping(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingApiOutput {
     ping_mod::send_ping(addr, timeout, data, options)
 }
 
+/// Send ICMP Echo package (ping) to an IPv6 address with its scope, such as a link-local one. See [`parse_scoped_ipv6`].
+pub fn send_ping_scoped(addr: &SocketAddrV6, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingApiOutput {
+    Pinger::new_scoped(addr, options)?.ping(timeout, data)
+}
+
+/// Parse an IPv6 address with an optional scope, given as an interface name or index: `fe80::1%eth0` or `fe80::1%2`.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let addr = ping_rs::parse_scoped_ipv6("fe80::1%eth0").unwrap();
+/// println!("{:?}", ping_rs::send_ping_scoped(&addr, Duration::from_secs(1), &[1,2,3,4], None));
+/// ```
+pub fn parse_scoped_ipv6(text: &str) -> Result<SocketAddrV6> {
+    let (address, scope) = match text.split_once('%') {
+        Some((address, scope)) => (address, Some(scope)),
+        None => (text, None)
+    };
+    let address: Ipv6Addr = address.parse().map_err(|_| PingError::BadParameter("address"))?;
+    let scope_id = match scope {
+        Some(scope) => scope.parse().or_else(|_| ping_mod::interface_index(scope)).map_err(|_| PingError::BadParameter("scope"))?,
+        None => 0
+    };
+    Ok(SocketAddrV6::new(address, 0, 0, scope_id))
+}
+
 /// Asynchronously schedule ICMP Echo package (ping) to the given address. Note that some parameter signatures are different
 /// from [`send_ping`] function, as the caller should manage those parameters' lifetime.
 #[inline(always)]
+#[allow(clippy::redundant_allocation)]
 pub async fn send_ping_async(addr: &IpAddr, timeout: Duration, data: Arc<&[u8]>, options: Option<&PingOptions>) -> PingApiOutput {
     ping_mod::send_ping_async(addr, timeout, data, options).await
 }
+
+/// Same as [`send_ping_async`], but the reply is awaited with the Tokio runtime's reactor and timer, so no extra thread is
+/// involved. It must be called from within a Tokio runtime with the time driver enabled.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let addr = "8.8.8.8".parse().unwrap();
+///     let result = ping_rs::send_ping_tokio(&addr, Duration::from_secs(1), &[1,2,3,4], None).await;
+///     println!("{result:?}");
+/// }
+/// ```
+#[cfg(all(unix, feature = "tokio"))]
+pub async fn send_ping_tokio(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingApiOutput {
+    ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping_tokio(timeout, data).await
+}
+
+/// Same as [`send_ping_async`], but the reply is awaited with the `async-io` reactor and an `async_io::Timer`, so it fits
+/// `smol` and `async-std` applications without an extra thread.
+#[cfg(all(unix, feature = "async-io"))]
+pub async fn send_ping_async_io(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingApiOutput {
+    ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping_async_io(timeout, data).await
+}
+
+/// A ping session to a single address. The session keeps one ICMP socket (an ICMP handle on Windows) open for all probes
+/// and increments the ICMP sequence number on every probe.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// let mut pinger = ping_rs::Pinger::new(&addr, None).unwrap();
+/// for _ in 0..4 {
+///     println!("{:?}", pinger.ping(Duration::from_secs(1), &[1,2,3,4]));
+/// }
+/// ```
+pub struct Pinger(ping_mod::Pinger);
+
+impl Pinger {
+    /// Open a ping session to the given address. `options` are applied to every probe of this session.
+    pub fn new(addr: &IpAddr, options: Option<&PingOptions>) -> Result<Pinger> {
+        ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), options).map(Pinger)
+    }
+
+    /// Same as [`Pinger::new`], for an IPv6 address with its scope, such as a link-local one. See [`parse_scoped_ipv6`].
+    pub fn new_scoped(addr: &SocketAddrV6, options: Option<&PingOptions>) -> Result<Pinger> {
+        ping_mod::Pinger::new(&SocketAddr::V6(*addr), options).map(Pinger)
+    }
+
+    /// Send the next ICMP Echo package and wait for its reply.
+    #[inline(always)]
+    pub fn ping(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.0.ping(timeout, data)
+    }
+
+    /// Asynchronously send the next ICMP Echo package. See [`send_ping_async`] for the parameters.
+    #[inline(always)]
+    #[allow(clippy::redundant_allocation)]
+    pub async fn ping_async(&mut self, timeout: Duration, data: Arc<&[u8]>) -> PingApiOutput {
+        self.0.ping_async(timeout, data).await
+    }
+
+    /// Send the next ICMP Echo package and await the reply on the Tokio runtime. See [`send_ping_tokio`].
+    #[cfg(all(unix, feature = "tokio"))]
+    #[inline(always)]
+    pub async fn ping_tokio(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.0.ping_tokio(timeout, data).await
+    }
+
+    /// Send the next ICMP Echo package and await the reply on the `async-io` reactor. See [`send_ping_async_io`].
+    #[cfg(all(unix, feature = "async-io"))]
+    #[inline(always)]
+    pub async fn ping_async_io(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.0.ping_async_io(timeout, data).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+    use std::io;
+    use crate::{IpStatus, PingError, parse_scoped_ipv6};
+
+    #[test]
+    fn ip_status_from_windows_value() {
+        assert_eq!(IpStatus::try_from(11013), Ok(IpStatus::TtlExpired));
+        assert_eq!(IpStatus::try_from(IpStatus::DestinationScopeMismatch as u32), Ok(IpStatus::DestinationScopeMismatch));
+        assert_eq!(IpStatus::try_from(11099), Err(11099));
+
+        assert!(IpStatus::TtlExpired.is_remote() && IpStatus::TtlExpired.is_permanent());
+        assert!(IpStatus::GeneralFailure.is_local() && IpStatus::GeneralFailure.is_permanent());
+        assert!(IpStatus::TimedOut.is_transient());
+        assert!(!IpStatus::Success.is_local() && !IpStatus::Success.is_permanent());
+    }
+
+    #[test]
+    fn unclassified_ip_status_is_neither_local_nor_remote() {
+        for status in [IpStatus::Success, IpStatus::TimedOut, IpStatus::BadRequest] {
+            assert!(!status.is_local() && !status.is_remote(), "{status:?}");
+        }
+    }
+
+    #[test]
+    fn parse_scoped_ipv6_address() {
+        let addr = parse_scoped_ipv6("fe80::1%7").unwrap();
+        assert_eq!((addr.ip().segments()[0], addr.scope_id()), (0xfe80, 7));
+        assert_eq!(parse_scoped_ipv6("fd00::1").unwrap().scope_id(), 0);
+
+        assert!(matches!(parse_scoped_ipv6("fe80::1%no-such-interface"), Err(PingError::BadParameter("scope"))));
+        assert!(matches!(parse_scoped_ipv6("10.0.0.1%7"), Err(PingError::BadParameter("address"))));
+    }
+
+    #[test]
+    fn os_error_is_the_source() {
+        let error = PingError::from(io::Error::from_raw_os_error(13));
+
+        assert_eq!(error.to_string(), format!("OS error: {}", io::Error::from_raw_os_error(13)));
+        assert_eq!(error.source().and_then(|e| e.downcast_ref::<io::Error>()).and_then(|e| e.raw_os_error()), Some(13));
+        assert_eq!(PingError::IpError(IpStatus::PacketTooBig).to_string(), "Packet too big");
+        assert!(PingError::TimedOut.source().is_none());
+    }
+}
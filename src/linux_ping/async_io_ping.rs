@@ -0,0 +1,26 @@
+#![cfg(feature = "async-io")]
+
+use std::io;
+use std::time::Duration;
+use async_io::{Async, Timer};
+use futures::future::{Either, select};
+use crate::linux_ping::Pinger;
+use crate::{PingApiOutput, PingError};
+
+impl Pinger {
+    pub async fn ping_async_io(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.context.set_nonblocking(true)?;
+        self.context.ping(timeout, data)?;
+
+        let context = &mut self.context;
+        let timer = Timer::at(context.deadline());
+        let socket = Async::new(context.socket.clone())?;
+        let reply = socket.read_with(|_| context.try_reply().ok_or_else(|| io::ErrorKind::WouldBlock.into()));
+        let result = match select(Box::pin(reply), timer).await {
+            Either::Left((result, _)) => result?,
+            Either::Right(_) => Err(PingError::TimedOut)
+        };
+        result
+    }
+}
+
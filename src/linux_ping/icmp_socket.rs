@@ -0,0 +1,319 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, SocketAddrV6},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    panic, process, thread,
+    sync::atomic::{AtomicU16, AtomicU32, Ordering},
+    time::{Duration, SystemTime},
+};
+use libc::c_int;
+use socket2::{SockAddr, Socket, Type};
+use crate::{Interface, IpStatus, PingError, PingOptions, Result, SocketType};
+use crate::linux_ping::icmp_header::ICMP_HEADER_SIZE;
+use crate::linux_ping::{Proto, RawPacketBuilder, SocketConfig, error_status, is_reply_of, local_error_status, make_data, set_request_data, sys, truncated_reply_size};
+
+/// A packet read from an [`IcmpSocket`].
+pub(crate) enum Packet<'a> {
+    /// An ICMP message, without the IP header that raw sockets deliver.
+    Message { message: &'a [u8], info: sys::Message },
+    /// An ICMP error about `request`, which was sent to `destination`. `offender` sent the error, and `mtu` is the next-hop
+    /// MTU it reported, if any.
+    Error { destination: Option<IpAddr>, request: &'a [u8], status: IpStatus, offender: Option<IpAddr>, mtu: Option<usize> },
+    /// Kernel timestamp of the request sent with `key`.
+    Transmitted { key: u32, at: Option<SystemTime> },
+}
+
+/// The ICMP socket of one address family, either an unprivileged datagram socket or a raw socket. It hides the differences
+/// between both: raw sockets need their own identifier, and IPv4 ones send and receive whole IP packets.
+pub(crate) struct IcmpSocket {
+    socket: Socket,
+    ident: u16,
+    options: PingOptions,
+    timestamps: bool,
+    sent: AtomicU32,
+
+    make_data: fn(&[u8], &mut Vec<u8>) -> Result<()>,
+    is_reply_of: fn(&[u8], &[u8]) -> bool,
+    truncated_reply_size: fn(&[u8], &[u8]) -> Option<usize>,
+    error_status: fn(&sys::ExtendedError) -> IpStatus,
+    icmp_message: fn(&[u8]) -> Result<&[u8]>,
+    raw_packet: Option<RawPacketBuilder>,
+}
+
+impl IcmpSocket {
+    /// Open a socket with `options` applied, or the default ones when `None`.
+    pub(crate) fn new<P: Proto>(options: Option<&PingOptions>) -> Result<IcmpSocket> {
+        let options = options.cloned().unwrap_or_default();
+        match options.netns.as_deref() {
+            // a thread cannot go back to its namespace once it left it, so a short-lived one is moved instead.
+            Some(netns) => thread::scope(|scope| {
+                scope.spawn(|| {
+                    sys::set_netns(netns).map_err(|e| privileged_error("netns", e))?;
+                    Self::open::<P>(&options)
+                }).join().unwrap_or_else(|e| panic::resume_unwind(e))
+            }),
+            None => Self::open::<P>(&options)
+        }
+    }
+
+    fn open<P: Proto>(options: &PingOptions) -> Result<IcmpSocket> {
+        if options.flow_label > MAX_FLOW_LABEL { return Err(PingError::BadParameter("flow_label")); }
+        let source = options.source;
+        if source.is_some_and(|s| s.is_ipv4() != P::UNSPECIFIED.is_ipv4()) { return Err(PingError::BadParameter("source")); }
+
+        let (socket, raw) = match options.socket_type {
+            SocketType::Datagram => (create_socket::<P>(Type::DGRAM)?, false),
+            SocketType::Raw => (create_socket::<P>(Type::RAW)?, true),
+            SocketType::Auto => match create_socket::<P>(Type::DGRAM) {
+                Ok(socket) => (socket, false),
+                // the process group is outside of `net.ipv4.ping_group_range`
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => (create_socket::<P>(Type::RAW).map_err(|_| e)?, true),
+                Err(e) => return Err(e.into())
+            }
+        };
+        sys::set_option(&socket, P::IP_LEVEL, P::RECV_ERROR, 1)?;
+        sys::set_option(&socket, P::IP_LEVEL, P::RECV_TTL, 1)?;
+        sys::set_option(&socket, P::IP_LEVEL, P::RECV_TOS, 1)?;
+        sys::set_option(&socket, P::IP_LEVEL, P::RECV_PKTINFO, 1)?;
+        if let Some(interface) = &options.interface {
+            bind_interface(&socket, interface)?;
+        }
+        P::set_ttl(&socket, options.ttl)?;
+        P::set_tos(&socket, options.tos)?;
+        P::set_flow_label(&socket, options.flow_label)?;
+        if let Some(mark) = options.mark {
+            set_privileged_option(&socket, "mark", libc::SO_MARK, mark)?;
+        }
+        if let Some(priority) = options.priority {
+            set_privileged_option(&socket, "priority", libc::SO_PRIORITY, priority)?;
+        }
+        sys::set_option(&socket, libc::SOL_SOCKET, libc::SO_DONTROUTE, options.dont_route as c_int)?;
+        P::set_dont_fragment(&socket, options.dont_fragment)?;
+        let timestamps = options.kernel_timestamps;
+        if timestamps {
+            sys::set_option(&socket, libc::SOL_SOCKET, libc::SO_TIMESTAMPING, TIMESTAMPING_FLAGS as c_int)?;
+        }
+
+        let raw_packet = if raw { P::RAW_PACKET } else { None };
+        if raw_packet.is_some() {
+            socket.set_header_included(true)?;
+        }
+        let ident = if raw {
+            if let Some(source) = source {
+                socket.bind(&SocketAddr::new(source, 0).into())?;
+            }
+            raw_ident()
+        } else {
+            bind_ident(&socket, source.unwrap_or(P::UNSPECIFIED))?
+        };
+
+        Ok(IcmpSocket {
+            socket, ident, options: options.clone(), timestamps, sent: AtomicU32::new(0),
+            make_data: make_data::<P>, is_reply_of: is_reply_of::<P>,
+            truncated_reply_size: truncated_reply_size::<P>, error_status: error_status::<P>,
+            icmp_message: if raw { P::get_icmp_message } else { whole_message },
+            raw_packet,
+        })
+    }
+
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// ICMP Echo request carrying `data`, numbered with `sequence`.
+    pub(crate) fn make_request(&self, data: &[u8], sequence: u16) -> Result<Vec<u8>> {
+        let mut request = Vec::new();
+        self.write_request(data, sequence, &mut request)?;
+        Ok(request)
+    }
+
+    /// Write the request of [`IcmpSocket::make_request`] to `request`, reusing its allocation.
+    pub(crate) fn write_request(&self, data: &[u8], sequence: u16, request: &mut Vec<u8>) -> Result<()> {
+        (self.make_data)(data, request)?;
+        set_request_data(request, self.ident, sequence);
+        Ok(())
+    }
+
+    /// Whether `message` is the echo reply of `request`.
+    pub(crate) fn is_reply_of(&self, request: &[u8], message: &[u8]) -> bool {
+        (self.is_reply_of)(request, message)
+    }
+
+    /// Data size of `message` when it is a truncated echo reply of `request`.
+    pub(crate) fn truncated_reply_size(&self, request: &[u8], message: &[u8]) -> Option<usize> {
+        (self.truncated_reply_size)(request, message)
+    }
+
+    /// Send `request`. The result is the key of its transmit timestamp.
+    pub(crate) fn send(&self, request: &[u8], destination: &SocketAddr) -> Result<u32> {
+        let addr: SockAddr = match destination {
+            // the flow label is in network byte order, which `SocketAddrV6` does not convert to
+            SocketAddr::V6(a) => SocketAddrV6::new(*a.ip(), 0, self.options.flow_label.to_be(), a.scope_id()).into(),
+            SocketAddr::V4(a) => SocketAddr::new(IpAddr::V4(*a.ip()), 0).into(),
+        };
+        let result = match self.raw_packet {
+            Some(build) => match send_to(&self.socket, &build(&destination.ip(), &self.options, request), &addr) {
+                // the kernel does not fragment packets that include their IP header, so let it build the header of this one.
+                Err(e) if is_too_big(&e) && !self.options.dont_fragment => {
+                    self.socket.set_header_included(false)?;
+                    let result = send_to(&self.socket, request, &addr);
+                    self.socket.set_header_included(true)?;
+                    result
+                },
+                result => result
+            },
+            None => send_to(&self.socket, request, &addr)
+        };
+        match result {
+            // e.g. bigger than the known path MTU while "Don't Fragment" is set, or no route
+            Err(e) => Err(e.raw_os_error().and_then(local_error_status).map_or_else(|| e.into(), PingError::IpError)),
+            // the kernel numbers the stamps of each sent packet
+            Ok(()) => Ok(self.sent.fetch_add(1, Ordering::Relaxed))
+        }
+    }
+
+    /// Read the next packet into `buffer`. A pending ICMP error fails the receive; it is then read from the socket error
+    /// queue instead.
+    pub(crate) fn receive<'a>(&self, buffer: &'a mut [u8]) -> Result<Packet<'a>> {
+        // transmit timestamps do not fail the receive, and are queued before the reply arrives.
+        if self.timestamps {
+            if let Ok(queued) = sys::recv_msg(&self.socket, buffer, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) {
+                return Ok(self.queued_packet(queued, buffer));
+            }
+        }
+        match sys::recv_msg(&self.socket, buffer, 0) {
+            Ok(message) => Ok(Packet::Message { message: (self.icmp_message)(&buffer[..message.size]).unwrap_or_default(), info: message }),
+            Err(e) => match sys::recv_msg(&self.socket, buffer, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) {
+                Ok(queued) => Ok(self.queued_packet(queued, buffer)),
+                Err(_) => Err(e.into())
+            }
+        }
+    }
+
+    /// Kernel timestamp of the request sent with `key`, if it is still in the socket error queue. Anything else queued
+    /// before it is discarded.
+    pub(crate) fn transmit_time(&self, key: u32, buffer: &mut [u8]) -> Option<SystemTime> {
+        if !self.timestamps { return None; }
+        while let Ok(queued) = sys::recv_msg(&self.socket, buffer, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) {
+            if let Packet::Transmitted { key: k, at } = self.queued_packet(queued, buffer) {
+                if k == key { return at; }
+            }
+        }
+        None
+    }
+
+    /// Path MTU of the last send that failed with `EMSGSIZE`, if it is still in the socket error queue. Anything else
+    /// queued before it is discarded.
+    pub(crate) fn local_mtu(&self) -> Option<usize> {
+        let mut buffer = [0; ICMP_HEADER_SIZE];
+        while let Ok(queued) = sys::recv_msg(&self.socket, &mut buffer, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) {
+            match queued.extended_error {
+                Some(error) if error.origin == libc::SO_EE_ORIGIN_LOCAL && error.errno == libc::EMSGSIZE as u32 => return Some(error.info as usize),
+                _ => ()
+            }
+        }
+        None
+    }
+
+    fn queued_packet<'a>(&self, queued: sys::Message, buffer: &'a [u8]) -> Packet<'a> {
+        match &queued.extended_error {
+            Some(error) if error.origin == libc::SO_EE_ORIGIN_TIMESTAMPING => Packet::Transmitted { key: error.data, at: queued.timestamp },
+            error => {
+                let status = error.as_ref().map_or(IpStatus::GeneralFailure, self.error_status);
+                Packet::Error {
+                    destination: queued.address.map(|a| a.ip()),
+                    request: (self.icmp_message)(&buffer[..queued.size]).unwrap_or_default(),
+                    status,
+                    offender: error.as_ref().and_then(|e| e.offender),
+                    mtu: error.as_ref().filter(|e| status == IpStatus::PacketTooBig && e.info != 0).map(|e| e.info as usize),
+                }
+            }
+        }
+    }
+}
+
+impl AsRawFd for IcmpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+// `socket2` 0.4 does not implement `AsFd`, which `async-io` requires.
+impl AsFd for IcmpSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // the fd is open as long as the socket is.
+        unsafe { BorrowedFd::borrow_raw(self.socket.as_raw_fd()) }
+    }
+}
+
+// INTERNAL
+
+/// IPv6 flow labels have 20 bits.
+const MAX_FLOW_LABEL: u32 = 0xFFFFF;
+
+/// Software transmit and receive timestamps. Transmit ones are numbered, and come without the sent packet.
+const TIMESTAMPING_FLAGS: u32 = libc::SOF_TIMESTAMPING_SOFTWARE | libc::SOF_TIMESTAMPING_TX_SOFTWARE | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_OPT_ID | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+
+fn create_socket<P: Proto>(r#type: Type) -> io::Result<Socket> {
+    let SocketConfig(domain, protocol) = P::SOCKET_CONFIG;
+    Socket::new_raw(domain, r#type, Some(protocol))
+}
+
+/// Ping sockets use the local "port" as the ICMP identifier, and the kernel only delivers replies with that identifier.
+/// Bind one of `source` now to learn it.
+fn bind_ident(socket: &Socket, source: IpAddr) -> Result<u16> {
+    socket.bind(&SocketAddr::new(source, 0).into())?;
+    Ok(socket.local_addr()?.as_socket().map(|a| a.port()).unwrap_or_default())
+}
+
+fn set_privileged_option(socket: &Socket, name: &'static str, option: c_int, value: u32) -> Result<()> {
+    sys::set_option(socket, libc::SOL_SOCKET, option, value as c_int).map_err(|e| privileged_error(name, e))
+}
+
+/// Error of applying the option `name`, which needs a capability.
+fn privileged_error(name: &'static str, e: io::Error) -> PingError {
+    if e.kind() == io::ErrorKind::PermissionDenied { PingError::PermissionDenied(name) } else { e.into() }
+}
+
+fn bind_interface(socket: &Socket, interface: &Interface) -> io::Result<()> {
+    match interface {
+        Interface::Name(name) => socket.bind_device(Some(name.as_bytes())),
+        Interface::Index(index) => socket.bind_device(Some(&sys::interface_name(*index)?)),
+    }
+}
+
+/// A pending ICMP error of an earlier request fails the next send once, so the send is retried when the socket shows one.
+fn send_to(socket: &Socket, packet: &[u8], addr: &SockAddr) -> io::Result<()> {
+    let sent = match socket.send_to(packet, addr) {
+        Err(_) if has_async_error(socket) => socket.send_to(packet, addr)?,
+        result => result?
+    };
+    if sent != packet.len() { return Err(io::Error::new(io::ErrorKind::WriteZero, format!("sent {sent} of {} bytes", packet.len()))); }
+    Ok(())
+}
+
+/// Whether `socket` shows an error that came after a send: in `SO_ERROR`, or in the error queue, unless it was read
+/// already. Transmit timestamps in the queue count too, which only costs a retry.
+fn has_async_error(socket: &Socket) -> bool {
+    matches!(socket.take_error(), Ok(Some(_))) || sys::has_error(socket).unwrap_or(false)
+}
+
+fn is_too_big(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EMSGSIZE)
+}
+
+/// Raw sockets receive the ICMP packets of the whole host, so each one identifies its requests with its own number.
+fn raw_ident() -> u16 {
+    static NEXT: AtomicU16 = AtomicU16::new(0);
+    (process::id() as u16).wrapping_add(NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Datagram sockets deliver the ICMP message only.
+fn whole_message(packet: &[u8]) -> Result<&[u8]> {
+    Ok(packet)
+}
@@ -4,41 +4,86 @@ mod v4;
 mod v6;
 mod icmp_header;
 mod ping_future;
-
-use std::io::Write;
-use std::mem;
-use std::mem::MaybeUninit;
+mod reactor;
+mod tokio_ping;
+mod async_io_ping;
+mod sys;
+mod icmp_socket;
+pub(crate) mod multi_pinger;
+pub(crate) mod periodic;
+
+use std::{io, mem};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use crate::{IpStatus, PingApiOutput, PingError, PingOptions, PingReply, Result};
-use crate::linux_ping::icmp_header::{ICMP_HEADER_SIZE, IcmpEchoHeader};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use libc::c_int;
+use socket2::{Domain, Protocol, Socket};
+use crate::{HopProbe, IpStatus, PingApiOutput, PingError, PingOptions, PingReply, Result};
+pub(crate) use crate::linux_ping::icmp_header::ICMP_HEADER_SIZE;
+use crate::linux_ping::icmp_header::IcmpEchoHeader;
 use crate::linux_ping::ping_future::{PingFuture};
-
-const TOKEN_SIZE: usize = 24;
+use crate::linux_ping::icmp_socket::{IcmpSocket, Packet};
 
 pub fn send_ping(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> Result<PingReply> {
-    let mut context = match addr {
-        IpAddr::V4(_) => PingContext::new::<Ipv4Addr>(addr, timeout, data, options)?,
-        IpAddr::V6(_) => PingContext::new::<Ipv6Addr>(addr, timeout, data, options)?,
-    };
-    context.ping()?;
-    let f = context.wait_reply.read().unwrap();
-    match f(&context.socket, context.start_ts) {
-        Err(PingError::IoPending) => Err(PingError::TimedOut),
-        v => v
-    }
+    Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping(timeout, data)
 }
 
+#[allow(clippy::redundant_allocation)]
 pub async fn send_ping_async(addr: &IpAddr, timeout: Duration, data: Arc<&[u8]>, options: Option<&PingOptions>) -> PingApiOutput {
-    let mut context = match addr {
-        IpAddr::V4(_) =>  PingContext::new::<Ipv4Addr>(addr, timeout, &data, options)?,
-        IpAddr::V6(_) =>  PingContext::new::<Ipv6Addr>(addr, timeout, &data, options)?,
-    };
-    context.socket.set_nonblocking(true)?;
-    context.ping()?;
-    PingFuture::new(context).await
+    Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping_async(timeout, data).await
+}
+
+pub(crate) struct Pinger {
+    context: PingContext,
+}
+
+impl Pinger {
+    pub fn new(addr: &SocketAddr, options: Option<&PingOptions>) -> Result<Pinger> {
+        Ok(Pinger { context: PingContext::new(addr, options)? })
+    }
+
+    pub fn ping(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.context.set_nonblocking(false)?;
+        self.context.ping(timeout, data)?;
+        self.context.socket.set_read_timeout(Some(self.context.timeout))?;
+        match wait_reply(&mut self.context) {
+            Err(PingError::IoPending) => Err(PingError::TimedOut),
+            v => v
+        }
+    }
+
+    #[allow(clippy::redundant_allocation)]
+    pub async fn ping_async(&mut self, timeout: Duration, data: Arc<&[u8]>) -> PingApiOutput {
+        self.context.set_nonblocking(true)?;
+        self.context.ping(timeout, &data)?;
+        PingFuture::new(&mut self.context).await
+    }
+
+    pub fn probe(&mut self, timeout: Duration, data: &[u8]) -> Result<HopProbe> {
+        let result = self.ping(timeout, data);
+        self.context.hop_probe(result)
+    }
+
+    #[allow(clippy::redundant_allocation)]
+    pub async fn probe_async(&mut self, timeout: Duration, data: Arc<&[u8]>) -> Result<HopProbe> {
+        let result = self.ping_async(timeout, data).await;
+        self.context.hop_probe(result)
+    }
+
+    /// MTU that came with the [`IpStatus::PacketTooBig`] failure of the last ping, if any.
+    pub fn next_hop_mtu(&self) -> Option<usize> {
+        self.context.next_hop_mtu
+    }
+}
+
+/// Index of the network interface `name`.
+pub(crate) fn interface_index(name: &str) -> io::Result<u32> {
+    sys::interface_index(name)
+}
+
+/// Name of `addr` by a reverse lookup.
+pub(crate) fn lookup_host_name(addr: &IpAddr) -> io::Result<String> {
+    sys::name_info(&SocketAddr::new(*addr, 0))
 }
 
 // INTERNAL
@@ -48,97 +93,254 @@ fn validate_timeout(timeout: Duration) -> Result<Duration> {
     else { Ok(timeout) }
 }
 
-type WaitReplyType = Arc<RwLock<Box<dyn Fn(&Socket, Instant) -> Result<PingReply> + Send + Sync>>>;
-
 pub(crate) struct PingContext {
-    ident: u16,
     sequence: u16,
     destination: SocketAddr,
     payload: Vec<u8>,
-    socket: Socket,
+    /// Receive buffer, kept with the payload from one request to the next
+    buffer: Vec<u8>,
+    socket: Arc<IcmpSocket>,
+    /// Whether the socket is in non-blocking mode, `None` until it is set
+    nonblocking: Option<bool>,
     timeout: Duration,
 
     start_ts: Instant,
-
-    wait_reply: WaitReplyType
+    sent_at: SystemTime,
+    transmit_key: u32,
+    transmitted_at: Option<SystemTime>,
+    /// Sender of the ICMP error about the request, and when it arrived
+    error_source: Option<(IpAddr, Duration)>,
+    /// MTU reported when the request was too big
+    next_hop_mtu: Option<usize>,
 }
 
-const MTU: usize = 1500;
+/// Largest IP packet, and so the largest a socket can deliver.
+pub(crate) const MAX_PACKET_SIZE: usize = 65535;
+
+/// Room for the IP header that raw IPv4 sockets deliver with the ICMP message.
+const IP_HEADER_ROOM: usize = 60;
+
 impl PingContext {
-    fn new<P: Proto>(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> Result<PingContext> {
-        let timeout = validate_timeout(timeout)?;
-        let payload = make_data::<P>(data)?;
+    fn new(addr: &SocketAddr, options: Option<&PingOptions>) -> Result<PingContext> {
+        let socket = match addr {
+            SocketAddr::V4(_) => IcmpSocket::new::<Ipv4Addr>(options)?,
+            SocketAddr::V6(_) => IcmpSocket::new::<Ipv6Addr>(options)?,
+        };
+        Ok(PingContext { sequence: 0, destination: *addr, payload: Vec::new(), buffer: Vec::new(), socket: Arc::new(socket),
+            nonblocking: None, timeout: Duration::ZERO,
+            start_ts: Instant::now(), sent_at: SystemTime::now(), transmit_key: 0, transmitted_at: None, error_source: None,
+            next_hop_mtu: None })
+    }
+
+    fn ping(&mut self, timeout: Duration, data: &[u8]) -> Result<()> {
+        self.timeout = validate_timeout(timeout)?;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.socket.write_request(data, self.sequence, &mut self.payload)?;
+
+        self.start_ts = Instant::now();
+        self.sent_at = SystemTime::now();
+        self.transmitted_at = None;
+        self.error_source = None;
+        self.next_hop_mtu = None;
+        let sent = self.socket.send(&self.payload, &self.destination);
+        if let Err(PingError::IpError(IpStatus::PacketTooBig)) = sent { self.next_hop_mtu = self.socket.local_mtu(); }
+        self.transmit_key = sent?;
+        Ok(())
+    }
 
-        let socket = create_socket::<P>()?;
-        if let Some(v) = options.map(|o| o.ttl) {
-            socket.set_ttl(v as u32)?;
+    /// Switch the socket to non-blocking mode or back, unless it is in that mode already.
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        if self.nonblocking != Some(nonblocking) {
+            self.socket.set_nonblocking(nonblocking)?;
+            self.nonblocking = Some(nonblocking);
         }
-        socket.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
 
-        let destination = SocketAddr::new(addr.clone(), 0);
-        let process_id = std::process::id() as u16;
+    fn deadline(&self) -> Instant {
+        self.start_ts + self.timeout
+    }
 
-        Ok(PingContext { ident: process_id, sequence: 0, destination, payload, socket, timeout, start_ts: Instant::now(),
-            wait_reply: Arc::new(RwLock::new(Box::new(|s,t| wait_reply::<P>(s,t)))) })
+    /// Traceroute probe of the request, from its `result`.
+    fn hop_probe(&self, result: PingApiOutput) -> Result<HopProbe> {
+        match (result, self.error_source) {
+            (Ok(reply), _) => Ok(HopProbe { address: reply.address, rtt: reply.rtt, status: IpStatus::Success }),
+            (Err(PingError::IpError(status)), Some((address, rtt))) => Ok(HopProbe { address, rtt, status }),
+            (Err(e), _) => Err(e)
+        }
     }
 
-    fn ping(&mut self) -> Result<()> {
-        self.sequence += 1;
-        set_request_data(&mut self.payload, self.ident, self.sequence);
+    /// Read the reply from a non-blocking socket, `None` when nothing is available yet.
+    fn try_reply(&mut self) -> Option<PingApiOutput> {
+        match wait_reply(self) {
+            Err(PingError::IoPending) => None,
+            result => Some(result)
+        }
+    }
+}
 
-        let addr: SockAddr = self.destination.into();
-        self.start_ts = Instant::now();
-        let sent = self.socket.send_to(&self.payload, &addr)?;
-        assert_eq!(sent, self.payload.len());
-        Ok(())
+/// Receive packets until the reply of the last request arrives. Other packets are discarded. On a blocking socket, this
+/// waits until the request deadline, with a read timeout of the request timeout set by the caller, so the last receive
+/// may end after the deadline when other packets came first; on a non-blocking one, [`PingError::IoPending`] is returned
+/// once nothing is left to read.
+fn wait_reply(context: &mut PingContext) -> Result<PingReply> {
+    let mut buffer = mem::take(&mut context.buffer);
+    buffer.resize(context.payload.len() + IP_HEADER_ROOM, 0);
+    let result = receive_reply(context, &mut buffer);
+    context.buffer = buffer;
+    result
+}
+
+fn receive_reply(context: &mut PingContext, buffer: &mut [u8]) -> Result<PingReply> {
+    loop {
+        if Instant::now() >= context.deadline() { return Err(PingError::TimedOut); }
+
+        match context.socket.receive(buffer)? {
+            Packet::Message { message, info } if context.socket.is_reply_of(&context.payload, message) => {
+                // a blocking receive returns the reply without reading the transmit timestamp queued before it. Timestamps
+                // come without the packet, so a small buffer reads them.
+                if context.transmitted_at.is_none() {
+                    context.transmitted_at = context.socket.transmit_time(context.transmit_key, &mut [0; ICMP_HEADER_SIZE]);
+                }
+                let address = info.address.unwrap_or(context.destination).ip();
+                return Ok(make_reply(address, message, &info, context.start_ts, context.sent_at, context.transmitted_at));
+            },
+            Packet::Message { message, .. } => if let Some(size) = context.socket.truncated_reply_size(&context.payload, message) {
+                return Err(PingError::TruncatedReply(size));
+            },
+            // ICMP errors may be about an earlier request too.
+            Packet::Error { request, status, offender, mtu, .. } if is_same_echo(&context.payload, request) => {
+                context.error_source = offender.map(|a| (a, context.start_ts.elapsed()));
+                context.next_hop_mtu = mtu;
+                return Err(PingError::IpError(status));
+            },
+            Packet::Transmitted { key, at } if key == context.transmit_key => context.transmitted_at = at,
+            _ => ()
+        }
     }
 }
 
-fn wait_reply<P: Proto>(socket: &Socket, start_ts: Instant) -> Result<PingReply> {
-    let mut buffer: [MaybeUninit<u8>; MTU] = unsafe { MaybeUninit::uninit().assume_init() };
-    let (size, addr) = socket.recv_from(&mut buffer)?;
-    debug_assert_ne!(size, 0);
-    let reply_buffer = unsafe { mem::transmute::<_, [u8; MTU]>(buffer) };
+/// Reply of a request sent at `start_ts` and `sent_at`, or at `transmitted_at` by the kernel timestamp. Both ends of the
+/// rtt come from the same clock: the kernel timestamps when the reply has one too, the user-space times otherwise.
+fn make_reply(address: IpAddr, message: &[u8], info: &sys::Message, start_ts: Instant, sent_at: SystemTime,
+              transmitted_at: Option<SystemTime>) -> PingReply {
+    let header = IcmpEchoHeader::get_ref(message);
+    let (sent_at, rtt) = match (transmitted_at, info.timestamp) {
+        (Some(transmitted_at), Some(received_at)) => (transmitted_at, received_at.duration_since(transmitted_at).unwrap_or_default()),
+        _ => (sent_at, start_ts.elapsed())
+    };
+    PingReply {
+        address,
+        scope_id: match info.address {
+            Some(SocketAddr::V6(a)) if a.scope_id() != 0 => Some(a.scope_id()),
+            _ => None
+        },
+        source: info.local_address,
+        rtt,
+        ttl: info.ttl,
+        tos: info.tos,
+        sequence: Some(header.seq()),
+        ident: Some(header.ident()),
+        size: message.len(),
+        data: message[ICMP_HEADER_SIZE..].to_vec(),
+        sent_at,
+        received_at: info.timestamp.unwrap_or_else(SystemTime::now),
+    }
+}
+
+/// Whether `reply` is the echo reply of `request`, i.e. it carries the same identifier, sequence and payload.
+fn is_reply_of<P: Proto>(request: &[u8], reply: &[u8]) -> bool {
+    is_same_echo(request, reply) && is_echo_reply::<P>(reply) && reply[ICMP_HEADER_SIZE..] == request[ICMP_HEADER_SIZE..]
+}
 
-    let header = IcmpEchoHeader::get_ref(&reply_buffer);
-    if header.r#type != P::ECHO_REPLY_TYPE || header.code != P::ECHO_REPLY_CODE { return Err(PingError::IpError(IpStatus::BadHeader)) }
+/// Data size of `reply` when it is the echo reply of `request`, but came back with only the beginning of its data.
+fn truncated_reply_size<P: Proto>(request: &[u8], reply: &[u8]) -> Option<usize> {
+    let is_truncated = is_same_echo(request, reply) && is_echo_reply::<P>(reply)
+        && reply.len() < request.len() && request[ICMP_HEADER_SIZE..].starts_with(&reply[ICMP_HEADER_SIZE..]);
+    is_truncated.then_some(reply.len() - ICMP_HEADER_SIZE)
+}
 
-    Ok(PingReply { address: addr.as_socket().unwrap().ip(), rtt: (start_ts.elapsed().as_secs_f64() * 1000.) as u32 })
+fn is_echo_reply<P: Proto>(packet: &[u8]) -> bool {
+    let header = IcmpEchoHeader::get_ref(packet);
+    header.r#type == P::ECHO_REPLY_TYPE && header.code == P::ECHO_REPLY_CODE
+}
+
+/// Whether `packet` starts with an ICMP Echo header of the same identifier and sequence as `request`.
+fn is_same_echo(request: &[u8], packet: &[u8]) -> bool {
+    if packet.len() < ICMP_HEADER_SIZE { return false; }
+
+    let (request_header, header) = (IcmpEchoHeader::get_ref(request), IcmpEchoHeader::get_ref(packet));
+    header.ident() == request_header.ident() && header.seq() == request_header.seq()
+}
+
+fn error_status<P: Proto>(error: &sys::ExtendedError) -> IpStatus {
+    if error.origin == P::ERROR_ORIGIN { return P::icmp_error_status(error.r#type, error.code); }
+    local_error_status(error.errno as c_int).unwrap_or(IpStatus::GeneralFailure)
+}
+
+/// Status of a request that this host failed to send with `errno`.
+fn local_error_status(errno: c_int) -> Option<IpStatus> {
+    match errno {
+        libc::EMSGSIZE => Some(IpStatus::PacketTooBig),
+        libc::ENETUNREACH => Some(IpStatus::DestinationNetworkUnreachable),
+        libc::EHOSTUNREACH => Some(IpStatus::DestinationHostUnreachable),
+        _ => None
+    }
 }
 
 struct SocketConfig(Domain, Protocol);
 
+/// Builds the whole IP packet of a request for raw sockets that include the IP header.
+type RawPacketBuilder = fn(&IpAddr, &PingOptions, &[u8]) -> Vec<u8>;
+
 // idea from tokio-ping
 trait Proto {
     const ECHO_REQUEST_TYPE: u8;
     const ECHO_REQUEST_CODE: u8;
     const ECHO_REPLY_TYPE: u8;
     const ECHO_REPLY_CODE: u8;
+    /// Largest data of an ICMP Echo request
+    const MAX_DATA_SIZE: usize;
     const SOCKET_CONFIG: SocketConfig;
+    const UNSPECIFIED: IpAddr;
+    const IP_LEVEL: c_int;
+    const RECV_ERROR: c_int;
+    const RECV_TTL: c_int;
+    const RECV_TOS: c_int;
+    const RECV_PKTINFO: c_int;
+    const ERROR_ORIGIN: u8;
+    const RAW_PACKET: Option<RawPacketBuilder>;
 
-    fn get_reply_header(reply: &[u8]) -> Result<&IcmpEchoHeader>;
-}
+    /// Map ICMP error type and code to [`IpStatus`].
+    fn icmp_error_status(r#type: u8, code: u8) -> IpStatus;
+
+    fn set_ttl(socket: &Socket, ttl: u8) -> io::Result<()>;
+
+    fn set_tos(socket: &Socket, tos: u8) -> io::Result<()>;
 
-fn create_socket<P: Proto>() -> Result<Socket> {
-    let SocketConfig(domain, protocol) = P::SOCKET_CONFIG;
-    Socket::new_raw(domain, Type::DGRAM, Some(protocol)).map_err(|x| x.into())
+    /// Send `flow_label` with the requests, for the protocols that have one.
+    fn set_flow_label(socket: &Socket, flow_label: u32) -> io::Result<()>;
+
+    fn set_dont_fragment(socket: &Socket, dont_fragment: bool) -> io::Result<()>;
+
+    /// ICMP message of a packet read from a raw socket.
+    fn get_icmp_message(packet: &[u8]) -> Result<&[u8]>;
 }
 
-fn make_data<P: Proto>(data: &[u8]) -> Result<Vec<u8>> {
-    if data.len() > TOKEN_SIZE { return Err(PingError::DataSizeTooBig(TOKEN_SIZE)); }
+/// Write the ICMP Echo request carrying `data` to `buffer`, reusing its allocation.
+fn make_data<P: Proto>(data: &[u8], buffer: &mut Vec<u8>) -> Result<()> {
+    if data.len() > P::MAX_DATA_SIZE { return Err(PingError::DataSizeTooBig(P::MAX_DATA_SIZE)); }
 
-    let mut buffer = vec![0; ICMP_HEADER_SIZE + data.len()];
-    let mut payload = &mut buffer[ICMP_HEADER_SIZE..];
-    if let Err(_) = payload.write(&data){
-        return Err(PingError::BadParameter("data"));
-    }
-    let header = IcmpEchoHeader::get_mut_ref(&mut buffer);
+    buffer.clear();
+    buffer.resize(ICMP_HEADER_SIZE, 0);
+    buffer.extend_from_slice(data);
+    let header = IcmpEchoHeader::get_mut_ref(buffer);
 
     header.r#type = P::ECHO_REQUEST_TYPE;
     header.code = P::ECHO_REQUEST_CODE;
-    write_checksum(&mut buffer);
+    write_checksum(buffer);
 
-    Ok(buffer)
+    Ok(())
 }
 
 fn set_request_data(data: &mut [u8], ident: u16, sequence: u16) {
@@ -149,6 +351,7 @@ fn set_request_data(data: &mut [u8], ident: u16, sequence: u16) {
 }
 
 fn write_checksum(buffer: &mut [u8]) {
+    IcmpEchoHeader::get_mut_ref(buffer).set_checksum(0);
     let mut sum = 0u32;
     for word in buffer.chunks(2) {
         let mut part = u16::from(word[0]) << 8;
@@ -169,15 +372,23 @@ fn write_checksum(buffer: &mut [u8]) {
 
 #[cfg(test)]
 mod test {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::{Duration, Instant, SystemTime};
+    use crate::{IpStatus, PingOptions};
     use crate::linux_ping::icmp_header::ICMP_HEADER_SIZE;
-    use crate::ping_mod::make_data;
+    use crate::ping_mod::{Proto, is_reply_of, make_data, make_reply, set_request_data, truncated_reply_size};
+    use crate::ping_mod::sys::Message;
+
+    fn make_data_vec(data: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut buffer = vec![];
+        make_data::<Ipv4Addr>(data, &mut buffer).map(|()| buffer)
+    }
 
     #[test]
     fn make_data_ok() {
         let data: &[u8; 4] = b"1234";
 
-        let result = make_data::<Ipv4Addr>(data);
+        let result = make_data_vec(data);
 
         // Assert
         let payload = result.unwrap();
@@ -185,4 +396,107 @@ mod test {
 
         assert_eq!(&payload[ICMP_HEADER_SIZE..], b"1234");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn is_reply_of_matches_ident_sequence_and_payload() {
+        let mut request = make_data_vec(b"1234").unwrap();
+        set_request_data(&mut request, 7, 3);
+        let reply = |ident, seq, data: &[u8]| {
+            let mut reply = make_data_vec(data).unwrap();
+            reply[0] = 0; // echo reply
+            set_request_data(&mut reply, ident, seq);
+            reply
+        };
+
+        // Assert
+        assert!(is_reply_of::<Ipv4Addr>(&request, &reply(7, 3, b"1234")));
+        assert!(!is_reply_of::<Ipv4Addr>(&request, &request));
+        assert!(!is_reply_of::<Ipv4Addr>(&request, &reply(8, 3, b"1234")));
+        assert!(!is_reply_of::<Ipv4Addr>(&request, &reply(7, 2, b"1234")));
+        assert!(!is_reply_of::<Ipv4Addr>(&request, &reply(7, 3, b"1235")));
+        assert!(!is_reply_of::<Ipv4Addr>(&request, &reply(7, 3, b"12")));
+    }
+
+    #[test]
+    fn truncated_reply_size_of_shorter_reply() {
+        let mut request = make_data_vec(b"1234").unwrap();
+        set_request_data(&mut request, 7, 3);
+        let mut reply = request.clone();
+        reply[0] = 0; // echo reply
+
+        // Assert
+        assert_eq!(truncated_reply_size::<Ipv4Addr>(&request, &reply[..ICMP_HEADER_SIZE+2]), Some(2));
+        assert_eq!(truncated_reply_size::<Ipv4Addr>(&request, &reply), None);
+        reply[ICMP_HEADER_SIZE] = b'9';
+        assert_eq!(truncated_reply_size::<Ipv4Addr>(&request, &reply[..ICMP_HEADER_SIZE+2]), None);
+    }
+
+    #[test]
+    fn make_reply_measures_rtt_between_kernel_timestamps() {
+        let mut message = make_data_vec(b"1234").unwrap();
+        set_request_data(&mut message, 7, 3);
+        let transmitted_at = SystemTime::now();
+        let info = Message { size: message.len(), address: None, local_address: None, extended_error: None, ttl: Some(63), tos: None,
+                             timestamp: Some(transmitted_at + Duration::from_micros(42)) };
+
+        let reply = make_reply(Ipv4Addr::LOCALHOST.into(), &message, &info, Instant::now(), transmitted_at - Duration::from_millis(1),
+                               Some(transmitted_at));
+
+        // Assert
+        assert_eq!(reply.rtt, Duration::from_micros(42));
+        assert_eq!(reply.sent_at, transmitted_at);
+        assert_eq!((reply.sequence, reply.ident, reply.ttl, reply.size), (Some(3), Some(7), Some(63), message.len()));
+        assert_eq!(reply.data, b"1234");
+    }
+
+    #[test]
+    fn make_reply_measures_rtt_in_user_space_without_receive_timestamp() {
+        let message = make_data_vec(b"1234").unwrap();
+        let info = Message { size: message.len(), address: None, local_address: None, extended_error: None, ttl: None, tos: None, timestamp: None };
+        let sent_at = SystemTime::now() - Duration::from_millis(5);
+        let start_ts = Instant::now() - Duration::from_millis(5);
+
+        let reply = make_reply(Ipv4Addr::LOCALHOST.into(), &message, &info, start_ts, sent_at, Some(SystemTime::now()));
+
+        // Assert
+        assert_eq!(reply.sent_at, sent_at);
+        assert!(reply.rtt >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn icmp_error_status_per_family() {
+        assert_eq!(Ipv4Addr::icmp_error_status(3, 1), IpStatus::DestinationHostUnreachable);
+        assert_eq!(Ipv4Addr::icmp_error_status(3, 0), IpStatus::DestinationNetworkUnreachable);
+        assert_eq!(Ipv4Addr::icmp_error_status(11, 0), IpStatus::TtlExpired);
+        assert_eq!(Ipv6Addr::icmp_error_status(1, 3), IpStatus::DestinationHostUnreachable);
+        assert_eq!(Ipv6Addr::icmp_error_status(1, 0), IpStatus::DestinationNetworkUnreachable);
+        assert_eq!(Ipv6Addr::icmp_error_status(3, 0), IpStatus::TtlExpired);
+    }
+
+    #[test]
+    fn get_icmp_message_skips_ipv4_options() {
+        let message = make_data_vec(b"1234").unwrap();
+        let mut packet = vec![0x46, 0, 0, 0, 0, 0, 0, 0, 64, 1];  // header of 6 words, protocol ICMP
+        packet.resize(24, 0);
+        packet.extend_from_slice(&message);
+
+        // Assert
+        assert_eq!(Ipv4Addr::get_icmp_message(&packet).ok(), Some(&message[..]));
+        assert!(Ipv4Addr::get_icmp_message(&packet[..24]).is_err());
+        packet[9] = 6;  // TCP
+        assert!(Ipv4Addr::get_icmp_message(&packet).is_err());
+    }
+
+    #[test]
+    fn raw_ipv4_packet_carries_options() {
+        let message = make_data_vec(b"1234").unwrap();
+        let options = PingOptions { ttl: 5, tos: 0xb8, dont_fragment: true, ..Default::default() };
+
+        let packet = Ipv4Addr::RAW_PACKET.unwrap()(&Ipv4Addr::new(10, 0, 0, 1).into(), &options, &message);
+
+        // Assert
+        assert_eq!((packet[1], packet[6], packet[8], packet[9]), (0xb8, 0x40, 5, 1));
+        assert_eq!(&packet[16..20], &[10, 0, 0, 1]);
+        assert_eq!(Ipv4Addr::get_icmp_message(&packet).ok(), Some(&message[..]));
+    }
+}
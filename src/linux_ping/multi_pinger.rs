@@ -0,0 +1,455 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    os::fd::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+use futures::FutureExt;
+use futures::channel::{mpsc, oneshot};
+use mio::Token;
+use crate::{IpStatus, PingApiOutput, PingError, PingOptions, Result};
+use crate::linux_ping::{MAX_PACKET_SIZE, Proto, is_same_echo, make_reply, validate_timeout};
+use crate::linux_ping::icmp_socket::{IcmpSocket, Packet};
+use crate::linux_ping::reactor::{Reactor, Readiness};
+use crate::linux_ping::icmp_header::{ICMP_HEADER_SIZE, IcmpEchoHeader};
+
+/// Ping many addresses concurrently through one ICMP socket per address family.
+///
+/// All echo requests share the same sockets, and the thread of the shared reactor receives the replies and routes each of
+/// them back to its waiting future by source address, identifier and sequence number. Dropping the `MultiPinger` stops
+/// watching the sockets.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use futures::future::join_all;
+///
+/// let pinger = ping_rs::MultiPinger::new(None).unwrap();
+/// let addrs: Vec<std::net::IpAddr> = vec!["8.8.8.8".parse().unwrap(), "1.1.1.1".parse().unwrap()];
+/// let pings = addrs.iter().map(|addr| pinger.ping(addr, Duration::from_secs(1), &[1,2,3,4]));
+/// for result in futures::executor::block_on(join_all(pings)) {
+///     println!("{result:?}");
+/// }
+/// ```
+pub struct MultiPinger {
+    shared: Arc<Shared>,
+    /// Reactor registrations of the sockets
+    tokens: Vec<Token>,
+}
+
+impl MultiPinger {
+    /// Open the shared sockets. `options` are applied to every probe. A family whose socket cannot be opened (e.g. no IPv6
+    /// on the host) only fails the pings to that family.
+    pub fn new(options: Option<&PingOptions>) -> Result<MultiPinger> {
+        Self::open(open_socket::<Ipv4Addr>(options), open_socket::<Ipv6Addr>(options))
+    }
+
+    /// Open the socket of the family of `addr` only, failing when it cannot be opened.
+    pub(crate) fn for_address(addr: &IpAddr, options: Option<&PingOptions>) -> Result<MultiPinger> {
+        let other_family = || Err(PingError::BadParameter("addr"));
+        match addr {
+            IpAddr::V4(_) => Self::open(Ok(open_socket::<Ipv4Addr>(options)?), other_family()),
+            IpAddr::V6(_) => Self::open(other_family(), Ok(open_socket::<Ipv6Addr>(options)?)),
+        }
+    }
+
+    fn open(v4: Result<IcmpSocket>, v6: Result<IcmpSocket>) -> Result<MultiPinger> {
+        let shared = Arc::new(Shared {
+            v4,
+            v6,
+            reactor: Reactor::get()?,
+            state: Mutex::new(State::default()),
+            buffer: Mutex::new(vec![0; MAX_PACKET_SIZE]),
+        });
+        let mut pinger = MultiPinger { shared, tokens: Vec::new() };
+        for (socket, watch) in [(&pinger.shared.v4, Watch::V4), (&pinger.shared.v6, Watch::V6)] {
+            if let Ok(socket) = socket {
+                let token = pinger.shared.watch(Some(socket.as_raw_fd()), None, watch)?;
+                pinger.tokens.push(token);
+            }
+        }
+        Ok(pinger)
+    }
+
+    /// Send an ICMP Echo package to `addr` and wait for its reply. Any number of pings can be in flight at the same time. A
+    /// request that finds the send buffer of the socket full fails at once with [`PingError::IoPending`].
+    pub async fn ping(&self, addr: &IpAddr, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.ping_to(&SocketAddr::new(*addr, 0), timeout, data).await
+    }
+
+    /// Same as [`MultiPinger::ping`], for an IPv6 address with its scope. See [`crate::parse_scoped_ipv6`].
+    pub async fn ping_scoped(&self, addr: &SocketAddrV6, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.ping_to(&SocketAddr::V6(*addr), timeout, data).await
+    }
+
+    async fn ping_to(&self, addr: &SocketAddr, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.send(addr, timeout, data, None)?.await
+    }
+
+    /// Check `timeout` and `data` for pings to `addr`, as sending them would.
+    pub(crate) fn validate(&self, addr: &IpAddr, timeout: Duration, data: &[u8]) -> Result<()> {
+        validate_timeout(timeout)?;
+        self.shared.socket(addr)?.make_request(data, 0).map(|_| ())
+    }
+
+    /// Send an ICMP Echo package to `addr` right away. The result waits for its reply. When it times out, a reply that still
+    /// comes within another `timeout` goes to `late`, if any, with its sequence number.
+    pub(crate) fn send(&self, addr: &SocketAddr, timeout: Duration, data: &[u8], late: Option<LateReplies>) -> Result<PendingReply> {
+        let (key, reply) = self.shared.send(addr, timeout, data, late)?;
+        Ok(PendingReply { shared: self.shared.clone(), key, reply })
+    }
+}
+
+/// Where replies that come after the timeout of their request go, with the sequence number of the request.
+pub(crate) type LateReplies = mpsc::UnboundedSender<(u16, PingApiOutput)>;
+
+/// Reply of a request sent by [`MultiPinger::send`]. The request is forgotten when this is dropped before the reply arrives.
+pub(crate) struct PendingReply {
+    shared: Arc<Shared>,
+    key: Key,
+    reply: oneshot::Receiver<PingApiOutput>,
+}
+
+impl Future for PendingReply {
+    type Output = PingApiOutput;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.reply.poll_unpin(cx).map(|reply| reply.unwrap_or(Err(PingError::IpError(IpStatus::GeneralFailure))))
+    }
+}
+
+impl PendingReply {
+    /// Sequence number of the request.
+    pub(crate) fn sequence(&self) -> u16 {
+        self.key.1
+    }
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        self.shared.remove(&mut state, &self.key);
+    }
+}
+
+impl Drop for MultiPinger {
+    fn drop(&mut self) {
+        for token in &self.tokens {
+            self.shared.reactor.deregister(*token);
+        }
+    }
+}
+
+// INTERNAL
+
+/// What a reactor registration of [`Shared`] watches.
+#[derive(Clone, Copy)]
+enum Watch {
+    V4,
+    V6,
+    /// The deadline of a request
+    Deadline(Key),
+    /// The end of the wait for a late reply
+    Forget(Key),
+}
+
+type Key = (IpAddr, u16);
+
+/// Socket and key of a transmit timestamp
+type TransmitKey = (RawFd, u32);
+
+fn open_socket<P: Proto>(options: Option<&PingOptions>) -> Result<IcmpSocket> {
+    let socket = IcmpSocket::new::<P>(options)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+struct Pending {
+    start_ts: Instant,
+    sent_at: SystemTime,
+    transmit_key: TransmitKey,
+    transmitted_at: Option<SystemTime>,
+    deadline: Instant,
+    /// Reactor registration of the deadline
+    timer: Token,
+    payload: Vec<u8>,
+    reply: oneshot::Sender<PingApiOutput>,
+    late: Option<LateReplies>,
+}
+
+/// A request that timed out, whose reply may still come.
+struct Expired {
+    start_ts: Instant,
+    sent_at: SystemTime,
+    transmitted_at: Option<SystemTime>,
+    payload: Vec<u8>,
+    late: LateReplies,
+    /// Reactor timer of the end of the wait for the reply
+    timer: Token,
+}
+
+#[derive(Default)]
+struct State {
+    sequence: u16,
+    pending: HashMap<Key, Pending>,
+    expired: HashMap<Key, Expired>,
+    transmitting: HashMap<TransmitKey, Key>,
+    /// Why replies are no longer received
+    error: Option<PingError>,
+}
+
+impl State {
+    /// Next sequence number that is not in flight to `addr`, nor waiting for a late reply.
+    fn next_sequence(&mut self, addr: &IpAddr) -> u16 {
+        loop {
+            self.sequence = self.sequence.wrapping_add(1);
+            let key = (*addr, self.sequence);
+            if !self.pending.contains_key(&key) && !self.expired.contains_key(&key) { return self.sequence; }
+        }
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Pending> {
+        let pending = self.pending.remove(key)?;
+        self.transmitting.remove(&pending.transmit_key);
+        Some(pending)
+    }
+}
+
+struct Shared {
+    v4: Result<IcmpSocket>,
+    v6: Result<IcmpSocket>,
+    reactor: &'static Reactor,
+    state: Mutex<State>,
+    /// Receive buffer of the reactor thread
+    buffer: Mutex<Vec<u8>>,
+}
+
+impl Shared {
+    fn socket(&self, addr: &IpAddr) -> Result<&IcmpSocket> {
+        match addr {
+            IpAddr::V4(_) => self.v4.as_ref(),
+            IpAddr::V6(_) => self.v6.as_ref(),
+        }.map_err(|e| e.clone())
+    }
+
+    fn send(self: &Arc<Self>, addr: &SocketAddr, timeout: Duration, data: &[u8], late: Option<LateReplies>)
+            -> Result<(Key, oneshot::Receiver<PingApiOutput>)> {
+        let timeout = validate_timeout(timeout)?;
+        let socket = self.socket(&addr.ip())?;
+        let (sender, receiver) = oneshot::channel();
+
+        // keep the lock while sending, so the reply cannot be received before the request is registered.
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = &state.error { return Err(error.clone()); }
+        let key = (addr.ip(), state.next_sequence(&addr.ip()));
+        let payload = socket.make_request(data, key.1)?;
+
+        let start_ts = Instant::now();
+        let sent_at = SystemTime::now();
+        let deadline = start_ts + timeout;
+        // a full send buffer fails the request rather than blocking the caller and the reactor thread with the lock held
+        let transmit_key = (socket.as_raw_fd(), socket.send(&payload, addr)?);
+        let timer = self.watch(None, Some(deadline), Watch::Deadline(key))?;
+
+        state.transmitting.insert(transmit_key, key);
+        state.pending.insert(key, Pending { start_ts, sent_at, transmit_key, transmitted_at: None, deadline, timer, payload, reply: sender, late });
+        Ok((key, receiver))
+    }
+
+    /// Register `fd` and `deadline` with the reactor, to be notified to [`Shared::notify`] as `watch`.
+    fn watch(self: &Arc<Self>, fd: Option<RawFd>, deadline: Option<Instant>, watch: Watch) -> io::Result<Token> {
+        let shared = Arc::downgrade(self);
+        self.reactor.register_callback(fd, deadline, Arc::new(move |result| if let Some(shared) = shared.upgrade() { shared.notify(watch, result) }))
+    }
+
+    fn notify(self: &Arc<Self>, watch: Watch, result: io::Result<Readiness>) {
+        match (watch, result) {
+            (_, Err(e)) => self.fail(e.into()),
+            (Watch::V4, Ok(_)) => if let Ok(socket) = &self.v4 { self.receive(socket) },
+            (Watch::V6, Ok(_)) => if let Ok(socket) = &self.v6 { self.receive(socket) },
+            (Watch::Deadline(key), Ok(_)) => self.expire(&key),
+            (Watch::Forget(key), Ok(_)) => self.forget(&key),
+        }
+    }
+
+    /// Forget the request of `key`.
+    fn remove(&self, state: &mut State, key: &Key) -> Option<Pending> {
+        let pending = state.remove(key)?;
+        self.reactor.deregister(pending.timer);
+        Some(pending)
+    }
+
+    /// Read all available replies and ICMP errors from the socket and hand them to their waiting futures.
+    fn receive(&self, socket: &IcmpSocket) {
+        let mut buffer = self.buffer.lock().unwrap();
+        loop {
+            let (addr, message, received) = match socket.receive(&mut buffer) {
+                Ok(Packet::Message { message, info }) => match info.address {
+                    Some(source) => (source.ip(), message, Ok(info)),
+                    None => continue
+                },
+                Ok(Packet::Error { destination: Some(destination), request, status, .. }) => (destination, request, Err(status)),
+                Ok(Packet::Transmitted { key, at }) => {
+                    self.transmitted((socket.as_raw_fd(), key), at);
+                    continue;
+                },
+                Ok(_) => continue,
+                Err(_) => break
+            };
+            if message.len() < ICMP_HEADER_SIZE { continue; }
+
+            let key = (addr, IcmpEchoHeader::get_ref(message).seq());
+            let outcome = |payload: &[u8]| match &received {
+                Ok(info) if socket.is_reply_of(payload, message) => Some(Ok(info)),
+                Ok(_) => socket.truncated_reply_size(payload, message).map(|size| Err(PingError::TruncatedReply(size))),
+                Err(status) => is_same_echo(payload, message).then_some(Err(PingError::IpError(*status)))
+            };
+            let mut state = self.state.lock().unwrap();
+            if let Some(outcome) = state.pending.get(&key).and_then(|p| outcome(&p.payload)) {
+                let pending = self.remove(&mut state, &key).unwrap();
+                drop(state);
+                let reply = outcome.map(|info| make_reply(addr, message, info, pending.start_ts, pending.sent_at, pending.transmitted_at));
+                let _ = pending.reply.send(reply);
+            } else if let Some(outcome) = state.expired.get(&key).and_then(|e| outcome(&e.payload)) {
+                let expired = state.expired.remove(&key).unwrap();
+                self.reactor.deregister(expired.timer);
+                drop(state);
+                let reply = outcome.map(|info| make_reply(addr, message, info, expired.start_ts, expired.sent_at, expired.transmitted_at));
+                let _ = expired.late.unbounded_send((key.1, reply));
+            }
+        }
+    }
+
+    /// Complete every request in flight with `error`, as no more replies will be received, and fail the later ones.
+    fn fail(&self, error: PingError) {
+        let mut state = self.state.lock().unwrap();
+        state.error = Some(error.clone());
+        let keys: Vec<Key> = state.pending.keys().copied().collect();
+        for key in keys {
+            if let Some(pending) = self.remove(&mut state, &key) {
+                let _ = pending.reply.send(Err(error.clone()));
+            }
+        }
+        for (_, expired) in state.expired.drain() {
+            self.reactor.deregister(expired.timer);
+        }
+    }
+
+    fn transmitted(&self, key: TransmitKey, at: Option<SystemTime>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(key) = state.transmitting.remove(&key) else { return };
+        if let Some(pending) = state.pending.get_mut(&key) {
+            pending.transmitted_at = at;
+        }
+    }
+
+    /// Time out the request of `key`, unless it was answered and its key reused since the deadline passed. A request whose
+    /// late reply is wanted is kept as long again as its timeout.
+    fn expire(self: &Arc<Self>, key: &Key) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.get(key).is_none_or(|p| p.deadline > Instant::now()) { return; }
+        let Some(pending) = self.remove(&mut state, key) else { return };
+        if let Some(late) = pending.late {
+            let forget_at = pending.deadline + (pending.deadline - pending.start_ts);
+            // without a timer, the late reply is not waited for
+            if let Ok(timer) = self.watch(None, Some(forget_at), Watch::Forget(*key)) {
+                state.expired.insert(*key, Expired {
+                    start_ts: pending.start_ts, sent_at: pending.sent_at, transmitted_at: pending.transmitted_at, payload: pending.payload,
+                    late, timer,
+                });
+            }
+        }
+        let _ = pending.reply.send(Err(PingError::TimedOut));
+    }
+
+    /// Stop waiting for the late reply of `key`.
+    fn forget(&self, key: &Key) {
+        if let Some(expired) = self.state.lock().unwrap().expired.remove(key) {
+            self.reactor.deregister(expired.timer);
+        }
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        for expired in self.state.get_mut().unwrap().expired.values() {
+            self.reactor.deregister(expired.timer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::net::IpAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime};
+    use futures::channel::{mpsc, oneshot};
+    use mio::Token;
+    use crate::PingError;
+    use crate::linux_ping::multi_pinger::{Pending, Shared, State, Watch};
+    use crate::linux_ping::reactor::{Reactor, Readiness};
+
+    #[test]
+    fn next_sequence_skips_in_flight() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let mut state = State::default();
+        let (reply, _) = oneshot::channel();
+        state.pending.insert((addr, 2), Pending { start_ts: Instant::now(), sent_at: SystemTime::now(), transmit_key: (0, 0), transmitted_at: None, deadline: Instant::now(), timer: Token(0), payload: vec![], reply, late: None });
+
+        // Act
+        let first = state.next_sequence(&addr);
+        let second = state.next_sequence(&addr);
+        let other = state.next_sequence(&"127.0.0.2".parse().unwrap());
+
+        // Assert
+        assert_eq!(first, 1);
+        assert_eq!(second, 3);
+        assert_eq!(other, 4);
+    }
+
+    #[test]
+    fn reactor_failure_completes_pending_requests() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let shared = Arc::new(Shared { v4: Err(PingError::TimedOut), v6: Err(PingError::TimedOut), reactor: Reactor::get().unwrap(),
+                                       state: Mutex::new(State::default()), buffer: Mutex::new(vec![]) });
+        let (reply, mut receiver) = oneshot::channel();
+        shared.state.lock().unwrap().pending.insert((addr, 1), Pending { start_ts: Instant::now(), sent_at: SystemTime::now(), transmit_key: (0, 0), transmitted_at: None, deadline: Instant::now(), timer: Token(0), payload: vec![], reply, late: None });
+
+        // Act
+        shared.notify(Watch::V4, Err(io::Error::other("poll failed")));
+
+        // Assert
+        assert!(matches!(receiver.try_recv(), Ok(Some(Err(PingError::OsError(_))))));
+        let state = shared.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert!(matches!(state.error, Some(PingError::OsError(_))));
+    }
+
+    #[test]
+    fn timed_out_request_waits_for_late_reply() {
+        let addr: IpAddr = "127.0.0.1".parse().unwrap();
+        let shared = Arc::new(Shared { v4: Err(PingError::TimedOut), v6: Err(PingError::TimedOut), reactor: Reactor::get().unwrap(),
+                                       state: Mutex::new(State::default()), buffer: Mutex::new(vec![]) });
+        let (reply, mut receiver) = oneshot::channel();
+        let (late, mut late_replies) = mpsc::unbounded();
+        let start_ts = Instant::now() - Duration::from_secs(1);
+        shared.state.lock().unwrap().pending.insert((addr, 1), Pending { start_ts, sent_at: SystemTime::now(), transmit_key: (0, 0), transmitted_at: None, deadline: Instant::now(), timer: Token(0), payload: vec![], reply, late: Some(late) });
+
+        // Act
+        shared.notify(Watch::Deadline((addr, 1)), Ok(Readiness::TimedOut));
+        let sequence = shared.state.lock().unwrap().next_sequence(&addr);
+
+        // Assert
+        assert!(matches!(receiver.try_recv(), Ok(Some(Err(PingError::TimedOut)))));
+        assert!(shared.state.lock().unwrap().expired.contains_key(&(addr, 1)));
+        assert_eq!(sequence, 2);
+        shared.notify(Watch::Forget((addr, 1)), Ok(Readiness::TimedOut));
+        assert!(shared.state.lock().unwrap().expired.is_empty());
+        assert!(matches!(late_replies.try_recv(), Err(mpsc::TryRecvError::Closed)));
+    }
+}
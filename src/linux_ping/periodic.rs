@@ -0,0 +1,169 @@
+//! Periodic pings sent by the reactor.
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+use futures::{FutureExt, Stream, StreamExt};
+use futures::channel::mpsc;
+use mio::Token;
+use crate::{PeriodicReply, PingApiOutput, PingError, PingOptions, Result, Schedule};
+use crate::linux_ping::multi_pinger::{LateReplies, MultiPinger, PendingReply};
+use crate::linux_ping::reactor::{Reactor, Readiness};
+
+/// Replies of requests sent on a schedule. A reactor timer sends each request when it is due, through the one socket of a
+/// [`MultiPinger`], so the requests go out on time whether the stream is polled or not, and stop once it is dropped. The
+/// late replies come through a channel from the [`MultiPinger`].
+pub(crate) enum PingStream {
+    Running(Arc<Shared>),
+    /// The stream could not start, for the error that is its only item.
+    Failed(Option<PingError>),
+}
+
+impl PingStream {
+    pub(crate) fn new(addr: &IpAddr, schedule: &Schedule, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingStream {
+        match Shared::start(addr, schedule, timeout, data, options) {
+            Ok(shared) => PingStream::Running(shared),
+            Err(e) => PingStream::Failed(Some(e))
+        }
+    }
+}
+
+impl Stream for PingStream {
+    type Item = PeriodicReply;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            PingStream::Running(shared) => shared.poll_next(cx),
+            PingStream::Failed(error) => Poll::Ready(error.take().map(|e| PeriodicReply { sequence: None, result: Err(e), late: false })),
+        }
+    }
+}
+
+impl Drop for PingStream {
+    fn drop(&mut self) {
+        if let PingStream::Running(shared) = self {
+            let mut state = shared.state.lock().unwrap();
+            state.closed = true;
+            state.late = None;
+            if let Some(token) = state.tick.take() { shared.reactor.deregister(token); }
+        }
+    }
+}
+
+// INTERNAL
+
+pub(crate) struct Shared {
+    pinger: MultiPinger,
+    reactor: &'static Reactor,
+    addr: SocketAddr,
+    schedule: Schedule,
+    timeout: Duration,
+    data: Vec<u8>,
+    /// When the first request is due
+    start: Instant,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Index of the next request
+    index: usize,
+    /// Reactor timer of the next request, `None` once the schedule is over
+    tick: Option<Token>,
+    /// Requests sent and not yet taken from the stream, in order
+    requests: VecDeque<Result<PendingReply>>,
+    /// Where the requests send their late replies, `None` once the schedule is over, so the channel ends with the last one
+    late: Option<LateReplies>,
+    late_replies: mpsc::UnboundedReceiver<(u16, PingApiOutput)>,
+    /// Task waiting for the next request to be sent
+    waker: Option<Waker>,
+    /// Whether the stream was dropped
+    closed: bool,
+}
+
+impl Shared {
+    fn start(addr: &IpAddr, schedule: &Schedule, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> Result<Arc<Shared>> {
+        let pinger = MultiPinger::for_address(addr, options)?;
+        pinger.validate(addr, timeout, data)?;
+        let (late, late_replies) = mpsc::unbounded();
+        let shared = Arc::new(Shared {
+            pinger,
+            reactor: Reactor::get()?,
+            addr: SocketAddr::new(*addr, 0),
+            schedule: *schedule,
+            timeout,
+            data: data.to_vec(),
+            start: Instant::now(),
+            state: Mutex::new(State {
+                index: 0, tick: None, requests: VecDeque::new(), late: Some(late), late_replies, waker: None, closed: false,
+            }),
+        });
+        shared.schedule_next(&mut shared.state.lock().unwrap())?;
+        Ok(shared)
+    }
+
+    /// Register the reactor timer of the next request, if the schedule has one.
+    fn schedule_next(self: &Arc<Self>, state: &mut State) -> io::Result<()> {
+        let Some(time) = self.schedule.time(self.start, state.index) else {
+            state.late = None;
+            return Ok(());
+        };
+        let shared = Arc::downgrade(self);
+        let callback = Arc::new(move |result| if let Some(shared) = shared.upgrade() { shared.tick(result) });
+        state.tick = Some(self.reactor.register_callback(None, Some(time), callback)?);
+        Ok(())
+    }
+
+    /// Send the request that is due and schedule the next one, or end the stream with the error of the reactor.
+    fn tick(self: &Arc<Self>, result: io::Result<Readiness>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed { return; }
+        if let Some(token) = state.tick.take() { self.reactor.deregister(token); }
+        match result {
+            Ok(_) => {
+                let request = self.pinger.send(&self.addr, self.timeout, &self.data, state.late.clone());
+                state.requests.push_back(request);
+                state.index += 1;
+                if let Err(e) = self.schedule_next(&mut state) {
+                    state.requests.push_back(Err(e.into()));
+                    state.late = None;
+                }
+            },
+            Err(e) => {
+                state.requests.push_back(Err(e.into()));
+                state.late = None;
+            }
+        }
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker { waker.wake(); }
+    }
+
+    /// Next result in the order of the requests. A late reply comes once the ones before it are taken, which include the
+    /// time out of its request.
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<PeriodicReply>> {
+        let state = &mut *self.state.lock().unwrap();
+        let reply = match state.requests.front_mut() {
+            Some(Ok(request)) => request.poll_unpin(cx).map(|result| PeriodicReply { sequence: Some(request.sequence()), result, late: false }),
+            Some(Err(e)) => Poll::Ready(PeriodicReply { sequence: None, result: Err(e.clone()), late: false }),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        };
+        if reply.is_ready() {
+            state.requests.pop_front();
+            return reply.map(Some);
+        }
+        match state.late_replies.poll_next_unpin(cx) {
+            Poll::Ready(Some((sequence, result))) => Poll::Ready(Some(PeriodicReply { sequence: Some(sequence), result, late: true })),
+            Poll::Ready(None) if state.requests.is_empty() && state.tick.is_none() => Poll::Ready(None),
+            _ => Poll::Pending
+        }
+    }
+}
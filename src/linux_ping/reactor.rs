@@ -0,0 +1,338 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    os::fd::RawFd,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Instant,
+};
+use mio::{Events, Interest, Registry, Token, unix::SourceFd};
+
+/// What a registration is waiting for turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Readiness {
+    Readable,
+    TimedOut,
+}
+
+/// Called on the reactor thread with every readiness of its registration, or with the error that stopped the reactor.
+pub(crate) type Callback = Arc<dyn Fn(io::Result<Readiness>) + Send + Sync>;
+
+/// A single background thread that polls all in-flight ping sockets with one `mio::Poll`, and notifies their owners either
+/// when the socket becomes readable or when the deadline passes. A registration is watched by a task through
+/// [`Reactor::poll_ready`], or by a [`Callback`].
+pub(crate) struct Reactor {
+    registry: Registry,
+    waker: mio::Waker,
+    state: Mutex<State>,
+}
+
+impl Reactor {
+    /// The process-wide reactor. Its thread is started on first use.
+    pub(crate) fn get() -> io::Result<&'static Reactor> {
+        static REACTOR: OnceLock<io::Result<Reactor>> = OnceLock::new();
+        let reactor = REACTOR.get_or_init(|| {
+            let (reactor, poll) = Reactor::new()?;
+            thread::Builder::new().name("ping-rs reactor".into()).spawn(move || Reactor::get().unwrap().run(poll))?;
+            Ok(reactor)
+        });
+        reactor.as_ref().map_err(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+
+    fn new() -> io::Result<(Reactor, mio::Poll)> {
+        let poll = mio::Poll::new()?;
+        let registry = poll.registry().try_clone()?;
+        let waker = mio::Waker::new(&registry, WAKE_TOKEN)?;
+        Ok((Reactor { registry, waker, state: Mutex::new(State::default()) }, poll))
+    }
+
+    /// Watch `fd` for readability until `deadline`, for a task that polls it with [`Reactor::poll_ready`].
+    pub(crate) fn register(&self, fd: RawFd, deadline: Instant) -> io::Result<Token> {
+        self.add(Some(fd), Some(deadline), Notify::Task { ready: false, timed_out: false, waker: None })
+    }
+
+    /// Watch `fd` for readability, if any, and `deadline`, if any, for `callback`. A deadline is notified once.
+    pub(crate) fn register_callback(&self, fd: Option<RawFd>, deadline: Option<Instant>, callback: Callback) -> io::Result<Token> {
+        self.add(fd, deadline, Notify::Callback(callback))
+    }
+
+    pub(crate) fn deregister(&self, token: Token) {
+        let mut state = self.state.lock().unwrap();
+        let Some(source) = state.sources.remove(&token) else { return };
+        if let Some(deadline) = source.deadline { state.deadlines.remove(&(deadline, token)); }
+        drop(state);
+        if let Some(fd) = source.fd { let _ = self.registry.deregister(&mut SourceFd(&fd)); }
+    }
+
+    /// Consume the readiness of `token`, or keep the task's waker for when it becomes ready.
+    pub(crate) fn poll_ready(&self, token: Token, cx: &mut Context<'_>) -> Poll<io::Result<Readiness>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = &state.error { return Poll::Ready(Err(error.to_io_error())); }
+        let Some(Source { notify: Notify::Task { ready, timed_out, waker }, .. }) = state.sources.get_mut(&token) else {
+            return Poll::Ready(Ok(Readiness::TimedOut))
+        };
+        if *ready {
+            *ready = false;
+            Poll::Ready(Ok(Readiness::Readable))
+        } else if *timed_out {
+            Poll::Ready(Ok(Readiness::TimedOut))
+        } else {
+            *waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn add(&self, fd: Option<RawFd>, deadline: Option<Instant>, notify: Notify) -> io::Result<Token> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = &state.error { return Err(error.to_io_error()); }
+        let token = state.next_token();
+        if let Some(fd) = fd { self.registry.register(&mut SourceFd(&fd), token, Interest::READABLE)?; }
+
+        let is_earliest = deadline.is_some_and(|deadline| state.next_deadline().is_none_or(|d| deadline < d));
+        if let Some(deadline) = deadline { state.deadlines.insert((deadline, token)); }
+        state.sources.insert(token, Source { fd, deadline, notify });
+        drop(state);
+
+        if is_earliest { self.waker.wake()?; }
+        Ok(token)
+    }
+
+    fn run(&self, mut poll: mio::Poll) {
+        let mut events = Events::with_capacity(1024);
+        loop {
+            let timeout = self.state.lock().unwrap().next_deadline().map(|d| d.saturating_duration_since(Instant::now()));
+            match poll.poll(&mut events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return self.fail(e),
+                Ok(()) => ()
+            }
+
+            let mut notifications = Notifications::default();
+            let mut state = self.state.lock().unwrap();
+            for event in events.iter().filter(|e| e.token() != WAKE_TOKEN) {
+                if let Some(source) = state.sources.get_mut(&event.token()) {
+                    notifications.add(&mut source.notify, Readiness::Readable);
+                }
+            }
+            let now = Instant::now();
+            while let Some(&(deadline, token)) = state.deadlines.first() {
+                if deadline > now { break; }
+                state.deadlines.pop_first();
+                if let Some(source) = state.sources.get_mut(&token) {
+                    source.deadline = None;
+                    notifications.add(&mut source.notify, Readiness::TimedOut);
+                }
+            }
+            drop(state);
+
+            notifications.send();
+        }
+    }
+
+    /// Stop for good on `error`: every registration is notified of it, and later ones fail with it.
+    fn fail(&self, error: io::Error) {
+        let error = ReactorError { kind: error.kind(), message: error.to_string() };
+        let mut state = self.state.lock().unwrap();
+        let (mut wakers, mut callbacks) = (Vec::new(), Vec::new());
+        for source in state.sources.values_mut() {
+            match &mut source.notify {
+                Notify::Task { waker, .. } => wakers.extend(waker.take()),
+                Notify::Callback(callback) => callbacks.push(callback.clone()),
+            }
+        }
+        state.deadlines.clear();
+        state.error = Some(error.clone());
+        drop(state);
+
+        wakers.into_iter().for_each(Waker::wake);
+        callbacks.into_iter().for_each(|callback| callback(Err(error.to_io_error())));
+    }
+}
+
+// INTERNAL
+
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+enum Notify {
+    /// A task polls the readiness with [`Reactor::poll_ready`].
+    Task { ready: bool, timed_out: bool, waker: Option<Waker> },
+    Callback(Callback),
+}
+
+struct Source {
+    fd: Option<RawFd>,
+    /// Not notified yet
+    deadline: Option<Instant>,
+    notify: Notify,
+}
+
+/// Notifications collected under the lock, to send once it is released.
+#[derive(Default)]
+struct Notifications {
+    wakers: Vec<Waker>,
+    callbacks: Vec<(Callback, Readiness)>,
+}
+
+impl Notifications {
+    fn add(&mut self, notify: &mut Notify, readiness: Readiness) {
+        match notify {
+            Notify::Task { ready, timed_out, waker } => {
+                match readiness {
+                    Readiness::Readable => *ready = true,
+                    Readiness::TimedOut => *timed_out = true,
+                }
+                self.wakers.extend(waker.take());
+            },
+            Notify::Callback(callback) => self.callbacks.push((callback.clone(), readiness)),
+        }
+    }
+
+    fn send(self) {
+        self.wakers.into_iter().for_each(Waker::wake);
+        self.callbacks.into_iter().for_each(|(callback, readiness)| callback(Ok(readiness)));
+    }
+}
+
+/// The error that stopped the reactor. `io::Error` cannot be cloned.
+#[derive(Clone)]
+struct ReactorError {
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl ReactorError {
+    fn to_io_error(&self) -> io::Error {
+        io::Error::new(self.kind, format!("ping reactor failed: {}", self.message))
+    }
+}
+
+#[derive(Default)]
+struct State {
+    last_token: usize,
+    sources: HashMap<Token, Source>,
+    deadlines: BTreeSet<(Instant, Token)>,
+    error: Option<ReactorError>,
+}
+
+impl State {
+    fn next_token(&mut self) -> Token {
+        loop {
+            self.last_token = self.last_token.wrapping_add(1) % WAKE_TOKEN.0;
+            let token = Token(self.last_token);
+            if !self.sources.contains_key(&token) { return token; }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.first().map(|(deadline, _)| *deadline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex, mpsc};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use futures::task::{ArcWake, waker};
+    use crate::linux_ping::reactor::{Reactor, Readiness};
+
+    struct WakeCount(AtomicUsize);
+
+    impl ArcWake for WakeCount {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// A reactor of its own, running on a thread of its own.
+    fn start_reactor() -> &'static Reactor {
+        let (reactor, poll) = Reactor::new().unwrap();
+        let reactor: &'static Reactor = Box::leak(Box::new(reactor));
+        thread::spawn(move || reactor.run(poll));
+        reactor
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "condition not met");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn readable_socket_wakes_task() {
+        let reactor = start_reactor();
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let token = reactor.register(reader.as_raw_fd(), Instant::now() + Duration::from_secs(60)).unwrap();
+        let count = Arc::new(WakeCount(AtomicUsize::new(0)));
+        let waker = waker(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(reactor.poll_ready(token, &mut cx).is_pending());
+
+        writer.write_all(&[1]).unwrap();
+
+        wait_for(|| count.0.load(Ordering::SeqCst) == 1);
+        assert!(matches!(reactor.poll_ready(token, &mut cx), Poll::Ready(Ok(Readiness::Readable))));
+        assert!(reactor.poll_ready(token, &mut cx).is_pending());
+    }
+
+    #[test]
+    fn deadline_times_out_task() {
+        let reactor = start_reactor();
+        let (_writer, reader) = UnixStream::pair().unwrap();
+        let token = reactor.register(reader.as_raw_fd(), Instant::now() + Duration::from_millis(20)).unwrap();
+        let count = Arc::new(WakeCount(AtomicUsize::new(0)));
+        let waker = waker(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(reactor.poll_ready(token, &mut cx).is_pending());
+
+        wait_for(|| count.0.load(Ordering::SeqCst) == 1);
+        assert!(matches!(reactor.poll_ready(token, &mut cx), Poll::Ready(Ok(Readiness::TimedOut))));
+    }
+
+    #[test]
+    fn deregistered_callback_is_not_called() {
+        let reactor = start_reactor();
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let token = reactor.register_callback(Some(reader.as_raw_fd()), None, Arc::new(move |r| sender.lock().unwrap().send(r.ok()).unwrap())).unwrap();
+
+        writer.write_all(&[1]).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), Some(Readiness::Readable));
+        reactor.deregister(token);
+        writer.write_all(&[2]).unwrap();
+
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(reactor.state.lock().unwrap().sources.is_empty());
+    }
+
+    #[test]
+    fn failure_completes_registrations() {
+        let (reactor, _poll) = Reactor::new().unwrap();
+        let (_writer, reader) = UnixStream::pair().unwrap();
+        let task = reactor.register(reader.as_raw_fd(), Instant::now() + Duration::from_secs(60)).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        reactor.register_callback(None, Some(Instant::now() + Duration::from_secs(60)), Arc::new(move |r| sender.lock().unwrap().send(r.is_err()).unwrap())).unwrap();
+        let count = Arc::new(WakeCount(AtomicUsize::new(0)));
+        let waker = waker(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(reactor.poll_ready(task, &mut cx).is_pending());
+
+        reactor.fail(io::Error::other("poll failed"));
+
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert!(receiver.try_recv().unwrap());
+        assert!(matches!(reactor.poll_ready(task, &mut cx), Poll::Ready(Err(_))));
+        assert!(reactor.register(reader.as_raw_fd(), Instant::now()).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Socket calls that `socket2` does not cover.
+
+use std::{io, mem, ptr};
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::path::Path;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::os::fd::AsRawFd;
+use libc::{c_int, c_void};
+use socket2::{SockAddr, Socket};
+
+/// `sock_extended_err` of an error queue message, see `ip(7)`.
+pub(crate) struct ExtendedError {
+    pub errno: u32,
+    pub origin: u8,
+    pub r#type: u8,
+    pub code: u8,
+    /// For "Packet too big" errors, the MTU of the next hop
+    pub info: u32,
+    /// For transmit timestamps, the key of the stamped packet
+    pub data: u32,
+    /// Sender of the ICMP error
+    pub offender: Option<IpAddr>,
+}
+
+/// A message read by `recvmsg`, with the ancillary data the ping sockets are configured to deliver.
+pub(crate) struct Message {
+    pub size: usize,
+    pub address: Option<SocketAddr>,
+    /// Destination address of the received packet
+    pub local_address: Option<IpAddr>,
+    pub extended_error: Option<ExtendedError>,
+    /// TTL or hop limit of the received packet
+    pub ttl: Option<u8>,
+    /// Type of service or traffic class of the received packet
+    pub tos: Option<u8>,
+    /// Software timestamp of the kernel
+    pub timestamp: Option<SystemTime>,
+}
+
+pub(crate) fn set_option(socket: &Socket, level: c_int, name: c_int, value: c_int) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(socket.as_raw_fd(), level, name, &value as *const c_int as *const c_void, mem::size_of::<c_int>() as libc::socklen_t)
+    };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Whether `socket` has a pending error or a message in its error queue, by a `poll` that does not wait.
+pub(crate) fn has_error(socket: &Socket) -> io::Result<bool> {
+    let mut fd = libc::pollfd { fd: socket.as_raw_fd(), events: 0, revents: 0 };
+    if unsafe { libc::poll(&mut fd, 1, 0) } < 0 { return Err(io::Error::last_os_error()); }
+    Ok(fd.revents & libc::POLLERR != 0)
+}
+
+/// Index of the network interface `name`.
+pub(crate) fn interface_index(name: &str) -> io::Result<u32> {
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index)
+    }
+}
+
+/// Host name of `addr`, by `getnameinfo`.
+pub(crate) fn name_info(addr: &SocketAddr) -> io::Result<String> {
+    let addr = SockAddr::from(*addr);
+    let mut name = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    let result = unsafe {
+        libc::getnameinfo(addr.as_ptr(), addr.len(), name.as_mut_ptr(), name.len() as libc::socklen_t, ptr::null_mut(), 0, libc::NI_NAMEREQD)
+    };
+    if result != 0 {
+        let message = unsafe { CStr::from_ptr(libc::gai_strerror(result)) };
+        return Err(io::Error::new(io::ErrorKind::NotFound, message.to_string_lossy()));
+    }
+    Ok(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+/// Name of the network interface with `index`.
+pub(crate) fn interface_name(index: u32) -> io::Result<Vec<u8>> {
+    let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+    if unsafe { libc::if_indextoname(index, name.as_mut_ptr()) }.is_null() { return Err(io::Error::last_os_error()); }
+    Ok(unsafe { CStr::from_ptr(name.as_ptr()) }.to_bytes().to_vec())
+}
+
+/// Move the calling thread into the network namespace at `path`.
+pub(crate) fn set_netns(path: &Path) -> io::Result<()> {
+    let netns = File::open(path)?;
+    if unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+pub(crate) fn recv_msg(socket: &Socket, buffer: &mut [u8], flags: c_int) -> io::Result<Message> {
+    let mut name: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut control = [0u64; CONTROL_SIZE / 8];
+    let mut iov = libc::iovec { iov_base: buffer.as_mut_ptr() as *mut c_void, iov_len: buffer.len() };
+
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = &mut name as *mut _ as *mut c_void;
+    header.msg_namelen = mem::size_of_val(&name) as libc::socklen_t;
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut c_void;
+    header.msg_controllen = CONTROL_SIZE as _;
+
+    let size = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut header, flags) };
+    if size < 0 { return Err(io::Error::last_os_error()); }
+
+    let address = if header.msg_namelen == 0 { None } else { unsafe { SockAddr::new(name, header.msg_namelen) }.as_socket() };
+    let mut message = Message { size: size as usize, address, local_address: None, extended_error: None, ttl: None, tos: None, timestamp: None };
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&header) };
+    while !cmsg.is_null() {
+        let (level, kind, data) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type, libc::CMSG_DATA(cmsg)) };
+        match (level, kind) {
+            (libc::SOL_IP, libc::IP_RECVERR) | (libc::SOL_IPV6, libc::IPV6_RECVERR) => {
+                let error = unsafe { ptr::read_unaligned(data as *const libc::sock_extended_err) };
+                let offender_size = unsafe { (*cmsg).cmsg_len } as usize - (data as usize - cmsg as usize) - mem::size_of_val(&error);
+                message.extended_error = Some(ExtendedError {
+                    errno: error.ee_errno, origin: error.ee_origin, r#type: error.ee_type, code: error.ee_code, info: error.ee_info, data: error.ee_data,
+                    offender: unsafe { read_address(data.add(mem::size_of_val(&error)), offender_size) },
+                });
+            },
+            (libc::SOL_IP, libc::IP_PKTINFO) => {
+                let info = unsafe { ptr::read_unaligned(data as *const libc::in_pktinfo) };
+                message.local_address = Some(Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)).into());
+            },
+            (libc::SOL_IPV6, libc::IPV6_PKTINFO) => {
+                let info = unsafe { ptr::read_unaligned(data as *const libc::in6_pktinfo) };
+                message.local_address = Some(Ipv6Addr::from(info.ipi6_addr.s6_addr).into());
+            },
+            (libc::SOL_IP, libc::IP_TTL) | (libc::SOL_IPV6, libc::IPV6_HOPLIMIT) => {
+                message.ttl = Some(unsafe { ptr::read_unaligned(data as *const c_int) } as u8);
+            },
+            // a byte for IPv4, but an int for IPv6
+            (libc::SOL_IP, libc::IP_TOS) => message.tos = Some(unsafe { *data }),
+            (libc::SOL_IPV6, libc::IPV6_TCLASS) => {
+                message.tos = Some(unsafe { ptr::read_unaligned(data as *const c_int) } as u8);
+            },
+            (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING) => {
+                // software, deprecated and hardware stamps, in this order
+                let stamps = unsafe { ptr::read_unaligned(data as *const [libc::timespec; 3]) };
+                let software = Duration::new(stamps[0].tv_sec as u64, stamps[0].tv_nsec as u32);
+                message.timestamp = (!software.is_zero()).then(|| UNIX_EPOCH + software);
+            },
+            _ => ()
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&header, cmsg) };
+    }
+    Ok(message)
+}
+
+// INTERNAL
+
+/// IP address of the `sockaddr` at `data`, which has `size` bytes.
+unsafe fn read_address(data: *const u8, size: usize) -> Option<IpAddr> {
+    if size < mem::size_of::<libc::sa_family_t>() { return None; }
+    match ptr::read_unaligned(data as *const libc::sa_family_t) as c_int {
+        libc::AF_INET if size >= mem::size_of::<libc::sockaddr_in>() => {
+            let addr = ptr::read_unaligned(data as *const libc::sockaddr_in);
+            Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into())
+        },
+        libc::AF_INET6 if size >= mem::size_of::<libc::sockaddr_in6>() => {
+            let addr = ptr::read_unaligned(data as *const libc::sockaddr_in6);
+            Some(Ipv6Addr::from(addr.sin6_addr.s6_addr).into())
+        },
+        _ => None
+    }
+}
+
+const CONTROL_SIZE: usize = 512;
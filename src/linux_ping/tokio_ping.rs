@@ -0,0 +1,30 @@
+#![cfg(feature = "tokio")]
+
+use std::io;
+use std::time::Duration;
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+use tokio::time::{Instant, timeout_at};
+use crate::linux_ping::Pinger;
+use crate::{PingApiOutput, PingError};
+
+impl Pinger {
+    pub async fn ping_tokio(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.context.set_nonblocking(true)?;
+        self.context.ping(timeout, data)?;
+
+        let context = &mut self.context;
+        let deadline = context.deadline();
+        let socket = AsyncFd::new(context.socket.clone())?;
+        let reply = async {
+            loop {
+                // ICMP errors are signalled as error readiness.
+                let mut guard = socket.ready(Interest::READABLE | Interest::ERROR).await?;
+                if let Ok(result) = guard.try_io(|_| context.try_reply().ok_or_else(|| io::ErrorKind::WouldBlock.into())) {
+                    return result?;
+                }
+            }
+        };
+        timeout_at(Instant::from_std(deadline), reply).await.unwrap_or(Err(PingError::TimedOut))
+    }
+}
@@ -1,10 +1,11 @@
-use std::net::Ipv4Addr;
-use socket2::{Domain, Protocol};
-use crate::linux_ping::{Proto, SocketConfig, Result};
-use crate::{IpStatus, PingError};
-use crate::linux_ping::icmp_header::{ICMP_HEADER_SIZE, IcmpEchoHeader};
+use std::net::{IpAddr, Ipv4Addr};
+use libc::c_int;
+use socket2::{Domain, Protocol, Socket};
+use crate::linux_ping::{Proto, RawPacketBuilder, SocketConfig, Result, sys};
+use crate::{IpStatus, PingError, PingOptions};
+use crate::linux_ping::icmp_header::ICMP_HEADER_SIZE;
 
-const ICMP_REPLY_HEADER_SIZE: usize = 20;
+const IP_HEADER_SIZE: usize = 20;
 
 // See https://en.wikipedia.org/wiki/Internet_Protocol_version_4#Header
 #[repr(C)]
@@ -13,12 +14,12 @@ struct IcmpV4ReplyHeader {
     _reserved1: [u8; 8],
     protocol: u8,
     _reserved2: [u8; 10],
-    reply: IcmpEchoHeader
 }
 
 impl IcmpV4ReplyHeader {
     fn version(&self) -> u8 { (self.version & 0xF0) >> 4 }
-    fn header_size(&self) -> usize { (self.version & 0x0F) as usize }
+    /// Header size in bytes, options included.
+    fn header_size(&self) -> usize { (self.version & 0x0F) as usize * 4 }
 }
 
 const ICMP_PROTOCOL: u8 = 1;
@@ -28,20 +29,85 @@ impl Proto for Ipv4Addr {
     const ECHO_REQUEST_CODE: u8 = 0;
     const ECHO_REPLY_TYPE: u8 = 0;
     const ECHO_REPLY_CODE: u8 = 0;
+    // IPv4 total length less the IP and ICMP headers
+    const MAX_DATA_SIZE: usize = 65535 - IP_HEADER_SIZE - ICMP_HEADER_SIZE;
     const SOCKET_CONFIG: SocketConfig = SocketConfig(Domain::IPV4, Protocol::ICMPV4);
+    const UNSPECIFIED: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    const IP_LEVEL: c_int = libc::IPPROTO_IP;
+    const RECV_ERROR: c_int = libc::IP_RECVERR;
+    const RECV_TTL: c_int = libc::IP_RECVTTL;
+    const RECV_TOS: c_int = libc::IP_RECVTOS;
+    const RECV_PKTINFO: c_int = libc::IP_PKTINFO;
+    const ERROR_ORIGIN: u8 = libc::SO_EE_ORIGIN_ICMP;
+    const RAW_PACKET: Option<RawPacketBuilder> = Some(build_packet);
 
-    fn get_reply_header(reply: &[u8]) -> Result<&IcmpEchoHeader> {
-        let reply_header = unsafe { &*(reply.as_ptr() as *const IcmpV4ReplyHeader) };
+    // See https://www.iana.org/assignments/icmp-parameters/icmp-parameters.xhtml
+    fn icmp_error_status(r#type: u8, code: u8) -> IpStatus {
+        match (r#type, code) {
+            (3, 0 | 6 | 11) => IpStatus::DestinationNetworkUnreachable,
+            (3, 1 | 7 | 12) => IpStatus::DestinationHostUnreachable,
+            (3, 2) => IpStatus::DestinationProtocolUnreachable,
+            (3, 3) => IpStatus::DestinationPortUnreachable,
+            (3, 4) => IpStatus::PacketTooBig,
+            (3, 5) => IpStatus::BadRoute,
+            (3, 9 | 10 | 13) => IpStatus::DestinationProhibited,
+            (3, _) => IpStatus::DestinationUnreachable,
+            (4, _) => IpStatus::SourceQuench,
+            (11, 0) => IpStatus::TtlExpired,
+            (11, 1) => IpStatus::TtlReassemblyTimeExceeded,
+            (11, _) => IpStatus::TimeExceeded,
+            (12, _) => IpStatus::ParameterProblem,
+            _ => IpStatus::IcmpError
+        }
+    }
+
+    fn set_ttl(socket: &Socket, ttl: u8) -> std::io::Result<()> {
+        socket.set_ttl(ttl as u32)
+    }
+
+    fn set_tos(socket: &Socket, tos: u8) -> std::io::Result<()> {
+        socket.set_tos(tos as u32)
+    }
+
+    fn set_flow_label(_socket: &Socket, _flow_label: u32) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_dont_fragment(socket: &Socket, dont_fragment: bool) -> std::io::Result<()> {
+        let discovery = if dont_fragment { libc::IP_PMTUDISC_DO } else { libc::IP_PMTUDISC_DONT };
+        sys::set_option(socket, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, discovery)
+    }
+
+    fn get_icmp_message(packet: &[u8]) -> Result<&[u8]> {
+        if packet.len() < IP_HEADER_SIZE { return Err(PingError::IpError(IpStatus::BadHeader)); }
 
-        println!("Reply len = {}", reply.len());
-        println!("Value: {reply:?}");
-        if reply.len() < ICMP_REPLY_HEADER_SIZE + ICMP_HEADER_SIZE
-            || reply_header.version() != 4
-            || reply.len() < reply_header.header_size()
-            || reply_header.protocol != ICMP_PROTOCOL
+        let header = unsafe { &*(packet.as_ptr() as *const IcmpV4ReplyHeader) };
+        let header_size = header.header_size();
+        if header.version() != 4
+            || header_size < IP_HEADER_SIZE
+            || packet.len() < header_size + ICMP_HEADER_SIZE
+            || header.protocol != ICMP_PROTOCOL
         {
             return Err(PingError::IpError(IpStatus::BadHeader));
         }
-        Ok(&reply_header.reply)
+        Ok(&packet[header_size..])
     }
-}
\ No newline at end of file
+}
+
+/// IPv4 packet of `request`. The kernel fills in the source address, identification and header checksum.
+fn build_packet(destination: &IpAddr, options: &PingOptions, request: &[u8]) -> Vec<u8> {
+    let IpAddr::V4(destination) = destination else { unreachable!("IPv4 socket") };
+    let mut packet = Vec::with_capacity(IP_HEADER_SIZE + request.len());
+    packet.push(0x45);  // version 4, header of 5 words
+    packet.push(options.tos);
+    packet.extend_from_slice(&((IP_HEADER_SIZE + request.len()) as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);  // identification
+    packet.extend_from_slice(&[if options.dont_fragment { 0x40 } else { 0 }, 0]);  // flags and fragment offset
+    packet.push(options.ttl);
+    packet.push(ICMP_PROTOCOL);
+    packet.extend_from_slice(&[0, 0]);  // header checksum
+    packet.extend_from_slice(&Ipv4Addr::UNSPECIFIED.octets());
+    packet.extend_from_slice(&destination.octets());
+    packet.extend_from_slice(request);
+    packet
+}
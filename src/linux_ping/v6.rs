@@ -1,7 +1,8 @@
-use std::net::Ipv6Addr;
-use socket2::{Domain, Protocol};
-use crate::linux_ping::{Proto, SocketConfig};
-use crate::linux_ping::icmp_header::{ICMP_HEADER_SIZE, IcmpEchoHeader};
+use std::net::{IpAddr, Ipv6Addr};
+use libc::c_int;
+use socket2::{Domain, Protocol, Socket};
+use crate::linux_ping::{Proto, RawPacketBuilder, SocketConfig, sys};
+use crate::linux_ping::icmp_header::ICMP_HEADER_SIZE;
 use crate::{IpStatus, PingError};
 
 impl Proto for Ipv6Addr {
@@ -9,10 +10,62 @@ impl Proto for Ipv6Addr {
     const ECHO_REQUEST_CODE: u8 = 0;
     const ECHO_REPLY_TYPE: u8 = 129;
     const ECHO_REPLY_CODE: u8 = 0;
+    // IPv6 payload length less the ICMP header
+    const MAX_DATA_SIZE: usize = 65535 - ICMP_HEADER_SIZE;
     const SOCKET_CONFIG: SocketConfig = SocketConfig(Domain::IPV6, Protocol::ICMPV6);
+    const UNSPECIFIED: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+    const IP_LEVEL: c_int = libc::IPPROTO_IPV6;
+    const RECV_ERROR: c_int = libc::IPV6_RECVERR;
+    const RECV_TTL: c_int = libc::IPV6_RECVHOPLIMIT;
+    const RECV_TOS: c_int = libc::IPV6_RECVTCLASS;
+    const RECV_PKTINFO: c_int = libc::IPV6_RECVPKTINFO;
+    const ERROR_ORIGIN: u8 = libc::SO_EE_ORIGIN_ICMP6;
+    const RAW_PACKET: Option<RawPacketBuilder> = None;
 
-    fn get_reply_header(reply: &[u8]) -> crate::Result<&IcmpEchoHeader> {
-        if reply.len() < ICMP_HEADER_SIZE { return Err(PingError::IpError(IpStatus::BadHeader)); }
-        Ok(IcmpEchoHeader::get_ref(reply))
+    // See https://www.iana.org/assignments/icmpv6-parameters/icmpv6-parameters.xhtml
+    fn icmp_error_status(r#type: u8, code: u8) -> IpStatus {
+        match (r#type, code) {
+            (1, 0) => IpStatus::DestinationNetworkUnreachable,
+            (1, 1 | 5 | 6) => IpStatus::DestinationProhibited,
+            (1, 2) => IpStatus::DestinationScopeMismatch,
+            (1, 3) => IpStatus::DestinationHostUnreachable,
+            (1, 4) => IpStatus::DestinationPortUnreachable,
+            (1, _) => IpStatus::DestinationUnreachable,
+            (2, _) => IpStatus::PacketTooBig,
+            (3, 0) => IpStatus::TtlExpired,
+            (3, 1) => IpStatus::TtlReassemblyTimeExceeded,
+            (3, _) => IpStatus::TimeExceeded,
+            (4, 0) => IpStatus::BadHeader,
+            (4, 1) => IpStatus::UnrecognizedNextHeader,
+            (4, _) => IpStatus::ParameterProblem,
+            _ => IpStatus::IcmpError
+        }
+    }
+
+    fn set_ttl(socket: &Socket, ttl: u8) -> std::io::Result<()> {
+        socket.set_unicast_hops_v6(ttl as u32)
+    }
+
+    fn set_tos(socket: &Socket, tos: u8) -> std::io::Result<()> {
+        sys::set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos as c_int)
+    }
+
+    // the label itself goes with the destination address of each request
+    fn set_flow_label(socket: &Socket, flow_label: u32) -> std::io::Result<()> {
+        sys::set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_FLOWINFO_SEND, (flow_label != 0) as c_int)
+    }
+
+    // IPv6 routers never fragment, so only fragmentation by this host can be turned off.
+    fn set_dont_fragment(socket: &Socket, dont_fragment: bool) -> std::io::Result<()> {
+        if dont_fragment {
+            sys::set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_DO)?;
+        }
+        sys::set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_DONTFRAG, dont_fragment as c_int)
+    }
+
+    // raw IPv6 sockets never deliver the IP header
+    fn get_icmp_message(packet: &[u8]) -> crate::Result<&[u8]> {
+        if packet.len() < ICMP_HEADER_SIZE { return Err(PingError::IpError(IpStatus::BadHeader)); }
+        Ok(packet)
     }
 }
@@ -0,0 +1,153 @@
+//! Continuous monitoring of the path to an address, like `mtr`.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::{Hop, HopProbe, Result, TraceOptions, traceroute, traceroute_async};
+
+/// Statistics of a hop over the rounds of a [`PathMonitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopStats {
+    /// TTL of the probes
+    pub ttl: u8,
+    /// Addresses that answered the probes, in the order they were first seen. More than one means the route changed or
+    /// is balanced.
+    pub addresses: Vec<IpAddr>,
+    /// Number of probes sent
+    pub sent: usize,
+    /// Number of probes answered
+    pub received: usize,
+    /// RTT of the last answer
+    pub last: Option<Duration>,
+    /// Mean RTT of the answers
+    pub average: Option<Duration>,
+    /// Smallest RTT
+    pub best: Option<Duration>,
+    /// Largest RTT
+    pub worst: Option<Duration>,
+    /// Mean difference between the RTTs of consecutive answers
+    pub jitter: Option<Duration>,
+    total_rtt: Duration,
+    total_jitter: Duration,
+}
+
+impl HopStats {
+    fn new(ttl: u8) -> Self {
+        HopStats { ttl, addresses: Vec::new(), sent: 0, received: 0, last: None, average: None, best: None, worst: None, jitter: None,
+                   total_rtt: Duration::ZERO, total_jitter: Duration::ZERO }
+    }
+
+    /// Ratio of the probes without answer, from 0 to 1.
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 { 0.0 } else { (self.sent - self.received) as f64 / self.sent as f64 }
+    }
+
+    fn record(&mut self, probe: Option<&HopProbe>) {
+        self.sent += 1;
+        let Some(probe) = probe else { return };
+        if !self.addresses.contains(&probe.address) { self.addresses.push(probe.address); }
+        if let Some(last) = self.last {
+            self.total_jitter += probe.rtt.abs_diff(last);
+            self.jitter = Some(self.total_jitter / self.received as u32);
+        }
+        self.received += 1;
+        self.total_rtt += probe.rtt;
+        self.last = Some(probe.rtt);
+        self.average = Some(self.total_rtt / self.received as u32);
+        self.best = Some(self.best.map_or(probe.rtt, |v| v.min(probe.rtt)));
+        self.worst = Some(self.worst.map_or(probe.rtt, |v| v.max(probe.rtt)));
+    }
+}
+
+/// Repeatedly traces the path to an address and keeps statistics of each hop.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// let mut monitor = ping_rs::PathMonitor::new(&addr, Duration::from_secs(1), &[1,2,3,4], &Default::default());
+/// loop {
+///     monitor.round().unwrap();
+///     for hop in monitor.hops() {
+///         println!("{} {:?} loss={:.0}% avg={:?} jitter={:?}", hop.ttl, hop.addresses, hop.loss() * 100.0, hop.average, hop.jitter);
+///     }
+/// }
+/// ```
+pub struct PathMonitor {
+    destination: IpAddr,
+    timeout: Duration,
+    data: Vec<u8>,
+    options: TraceOptions,
+    hops: Vec<HopStats>,
+}
+
+impl PathMonitor {
+    /// Monitor the path to `destination`. Every round traces it with [`traceroute`] and these parameters.
+    pub fn new(destination: &IpAddr, timeout: Duration, data: &[u8], options: &TraceOptions) -> Self {
+        PathMonitor { destination: *destination, timeout, data: data.to_vec(), options: options.clone(), hops: Vec::new() }
+    }
+
+    /// Statistics of the hops, up to the last hop of the latest round.
+    pub fn hops(&self) -> &[HopStats] {
+        &self.hops
+    }
+
+    /// Trace the path once and add the probes to the statistics.
+    pub fn round(&mut self) -> Result<()> {
+        let hops = traceroute(&self.destination, self.timeout, &self.data, &self.options)?;
+        self.update(&hops);
+        Ok(())
+    }
+
+    /// Same as [`PathMonitor::round`], but asynchronous.
+    pub async fn round_async(&mut self) -> Result<()> {
+        let hops = traceroute_async(&self.destination, self.timeout, Arc::new(&self.data), &self.options).await?;
+        self.update(&hops);
+        Ok(())
+    }
+
+    /// Hops past the last one of `hops` are dropped, as the route no longer goes through them.
+    fn update(&mut self, hops: &[Hop]) {
+        self.hops.truncate(hops.len());
+        for hop in hops {
+            let index = hop.ttl as usize - 1;
+            if index == self.hops.len() { self.hops.push(HopStats::new(hop.ttl)); }
+            for probe in &hop.probes {
+                self.hops[index].record(probe.as_ref().ok());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+    use crate::{Hop, HopProbe, IpStatus, PingError, TraceOptions};
+    use crate::monitor::PathMonitor;
+
+    #[test]
+    fn update_keeps_stats_of_current_hops() {
+        let probe = |host, millis, status| Ok(HopProbe { address: IpAddr::from(Ipv4Addr::new(10, 0, 0, host)), rtt: Duration::from_millis(millis), status });
+        let mut monitor = PathMonitor::new(&IpAddr::from(Ipv4Addr::new(10, 0, 0, 3)), Duration::from_secs(1), &[], &TraceOptions::default());
+        monitor.update(&[
+            Hop { ttl: 1, probes: vec![probe(1, 10, IpStatus::TtlExpired), probe(1, 30, IpStatus::TtlExpired), Err(PingError::TimedOut)] },
+            Hop { ttl: 2, probes: vec![probe(2, 5, IpStatus::TtlExpired)] },
+            Hop { ttl: 3, probes: vec![probe(3, 5, IpStatus::Success)] },
+        ]);
+        monitor.update(&[
+            Hop { ttl: 1, probes: vec![probe(4, 20, IpStatus::TtlExpired)] },
+            Hop { ttl: 2, probes: vec![probe(3, 5, IpStatus::Success)] },
+        ]);
+
+        let hops = monitor.hops();
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].addresses, vec![IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)), IpAddr::from(Ipv4Addr::new(10, 0, 0, 4))]);
+        assert_eq!((hops[0].sent, hops[0].received, hops[0].loss()), (4, 3, 0.25));
+        assert_eq!(hops[0].last, Some(Duration::from_millis(20)));
+        assert_eq!(hops[0].average, Some(Duration::from_millis(20)));
+        assert_eq!((hops[0].best, hops[0].worst), (Some(Duration::from_millis(10)), Some(Duration::from_millis(30))));
+        assert_eq!(hops[0].jitter, Some(Duration::from_millis(15)));
+        assert_eq!(hops[1].received, 2);
+    }
+}
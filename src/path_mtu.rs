@@ -0,0 +1,145 @@
+//! Path MTU discovery with "Don't Fragment" echo requests.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::{IpStatus, PingError, PingOptions, Result, ping_mod};
+use crate::ping_mod::{ICMP_HEADER_SIZE, MAX_PACKET_SIZE};
+
+/// Find the MTU of the path to `addr`: the size of the largest IP packet that reaches it unfragmented, headers included.
+/// Echo requests with "Don't Fragment" set are binary-searched by size. A next-hop MTU reported by a "Packet too big"
+/// error is probed right away. A size is probed again when its request goes unanswered, and counts as too big when that
+/// happens again, as on a path that drops those errors (a black hole). Each probe waits up to `timeout`; `dont_fragment` of
+/// `options` is ignored.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// println!("{:?}", ping_rs::discover_path_mtu(&addr, Duration::from_secs(1), None));
+/// ```
+pub fn discover_path_mtu(addr: &IpAddr, timeout: Duration, options: Option<&PingOptions>) -> Result<usize> {
+    let options = PingOptions { dont_fragment: true, ..options.cloned().unwrap_or_default() };
+    let mut pinger = ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), Some(&options))?;
+    let mut search = MtuSearch::new(addr);
+    while let Some(size) = search.next_size() {
+        let result = pinger.ping(timeout, &vec![0; size - search.header_size]).map(|_| ());
+        search.update(result, pinger.next_hop_mtu())?;
+    }
+    search.result()
+}
+
+/// Same as [`discover_path_mtu`], but asynchronous.
+pub async fn discover_path_mtu_async(addr: &IpAddr, timeout: Duration, options: Option<&PingOptions>) -> Result<usize> {
+    let options = PingOptions { dont_fragment: true, ..options.cloned().unwrap_or_default() };
+    let mut pinger = ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), Some(&options))?;
+    let mut search = MtuSearch::new(addr);
+    while let Some(size) = search.next_size() {
+        let data = vec![0; size - search.header_size];
+        let result = pinger.ping_async(timeout, Arc::new(&data)).await.map(|_| ());
+        search.update(result, pinger.next_hop_mtu())?;
+    }
+    search.result()
+}
+
+// INTERNAL
+
+/// IPv4 header without options
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+
+/// Unanswered probes of a size after which it counts as too big, since a single request may just be lost.
+const MAX_TIMEOUTS: usize = 2;
+
+/// Binary search of the path MTU, in packet sizes.
+struct MtuSearch {
+    /// IP and ICMP headers of a request
+    header_size: usize,
+    /// Largest size that was answered, or one below the smallest request when none was
+    low: usize,
+    /// Largest size that may be answered
+    high: usize,
+    /// Size of the next probe
+    size: usize,
+    /// Failure of the last probe that was too big
+    error: PingError,
+    /// Unanswered probes of `size` in a row
+    timeouts: usize,
+}
+
+impl MtuSearch {
+    fn new(addr: &IpAddr) -> Self {
+        let header_size = if addr.is_ipv4() { IPV4_HEADER_SIZE } else { IPV6_HEADER_SIZE } + ICMP_HEADER_SIZE;
+        MtuSearch { header_size, low: header_size - 1, high: MAX_PACKET_SIZE, size: MAX_PACKET_SIZE, error: PingError::TimedOut, timeouts: 0 }
+    }
+
+    fn next_size(&self) -> Option<usize> {
+        (self.low < self.high).then_some(self.size)
+    }
+
+    /// Take the `result` of the probe of the last size, which failed with `mtu` if it reported one.
+    fn update(&mut self, result: Result<()>, mtu: Option<usize>) -> Result<()> {
+        match result {
+            Ok(()) | Err(PingError::TruncatedReply(_)) => self.low = self.size,
+            Err(PingError::TimedOut) if self.timeouts + 1 < MAX_TIMEOUTS => {
+                self.timeouts += 1;
+                return Ok(());
+            },
+            Err(e @ (PingError::IpError(IpStatus::PacketTooBig) | PingError::TimedOut)) => {
+                self.high = self.size - 1;
+                self.error = e;
+            },
+            Err(PingError::DataSizeTooBig(max)) => self.high = self.high.min(max + self.header_size),
+            Err(e) => return Err(e)
+        }
+        self.timeouts = 0;
+        self.size = match mtu {
+            Some(mtu) if self.low < mtu && mtu <= self.high => {
+                self.high = mtu;
+                mtu
+            },
+            _ => (self.low + self.high).div_ceil(2)
+        };
+        Ok(())
+    }
+
+    fn result(self) -> Result<usize> {
+        if self.low < self.header_size { Err(self.error) } else { Ok(self.low) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use crate::{IpStatus, PingError, Result};
+    use crate::path_mtu::MtuSearch;
+
+    /// Path MTU found on a path of `mtu`, whose router reports it when `reports` is set, and the number of probes.
+    fn search(mtu: usize, reports: bool) -> (Result<usize>, usize) {
+        let mut search = MtuSearch::new(&IpAddr::from(Ipv4Addr::LOCALHOST));
+        let mut probes = 0;
+        while let Some(size) = search.next_size() {
+            probes += 1;
+            let result = if size <= mtu { Ok(()) } else if reports { Err(PingError::IpError(IpStatus::PacketTooBig)) } else { Err(PingError::TimedOut) };
+            search.update(result, reports.then_some(mtu)).unwrap();
+        }
+        (search.result(), probes)
+    }
+
+    #[test]
+    fn search_honours_reported_mtu_and_black_holes() {
+        assert!(matches!(search(1400, true), (Ok(1400), 2)));
+        assert!(matches!(search(1400, false), (Ok(1400), _)));
+        assert!(matches!(search(65535, false), (Ok(65535), 1)));
+        assert!(matches!(search(27, false), (Err(PingError::TimedOut), _)));
+    }
+
+    #[test]
+    fn search_retries_lost_probe() {
+        let mut search = MtuSearch::new(&IpAddr::from(Ipv4Addr::LOCALHOST));
+        search.update(Err(PingError::TimedOut), None).unwrap();
+        assert_eq!(search.next_size(), Some(65535));
+        search.update(Ok(()), None).unwrap();
+        assert!(matches!(search.result(), Ok(65535)));
+    }
+}
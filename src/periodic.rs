@@ -0,0 +1,253 @@
+//! Pings sent periodically to an address.
+
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+use futures::Stream;
+use futures::executor::block_on;
+use futures::future::{self, Either};
+use crate::{PingApiOutput, PingOptions, Result, ping_mod};
+
+/// When periodic pings are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Time between the requests. It is kept from the first request, so late requests do not delay the next ones.
+    pub interval: Duration,
+    /// Number of requests, unlimited when `None`
+    pub count: Option<usize>,
+    /// Time from the first request after which no more are sent, unlimited when `None`
+    pub deadline: Option<Duration>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule { interval: Duration::from_secs(1), count: None, deadline: None }
+    }
+}
+
+impl Schedule {
+    /// Start time of the request `index`, counting from 0, when the first one is sent at `start`. `None` once the
+    /// schedule is over.
+    pub(crate) fn time(&self, start: Instant, index: usize) -> Option<Instant> {
+        if self.count.is_some_and(|count| index >= count) { return None; }
+        let offset = self.interval.checked_mul(u32::try_from(index).ok()?)?;
+        self.deadline.is_none_or(|deadline| offset <= deadline).then(|| start + offset)
+    }
+
+    /// Index of the request to send at `now`, from `next` on: the last one that is due, so the ones whose time passed are
+    /// skipped.
+    fn due(&self, start: Instant, now: Instant, next: usize) -> usize {
+        let mut index = next;
+        if self.interval.is_zero() { return index; }
+        while self.time(start, index + 1).is_some_and(|time| time <= now) { index += 1; }
+        index
+    }
+}
+
+/// Result of a request of [`ping_stream`].
+#[derive(Debug, Clone)]
+pub struct PeriodicReply {
+    /// Sequence number of the request, `None` on Windows and for a request that could not be sent
+    pub sequence: Option<u16>,
+    pub result: PingApiOutput,
+    /// Whether the reply came after the request timed out, which was reported before as
+    /// [`PingError::TimedOut`](crate::PingError::TimedOut)
+    pub late: bool,
+}
+
+/// Ping `addr` on `schedule`, through one socket (one ICMP handle on Windows). Each request waits up to `timeout` for its
+/// reply, while the next ones are sent on time, so a reply may come after later requests were sent. There is one result
+/// per request, in the order of the requests: its reply, or [`PingError::TimedOut`](crate::PingError::TimedOut) when no
+/// reply came within `timeout`. On Linux, a reply that comes within another `timeout` after that is reported too, as an
+/// extra result with `late` set; Windows drops it. The stream ends once the last request is answered or timed out, and no
+/// late reply can come anymore, or after the error that stops the requests.
+///
+/// On Linux, the requests are sent by the background thread that receives the replies, whether the stream is polled or
+/// not. That thread never blocks: a request that does not fit in the send buffer of the socket fails with
+/// [`PingError::IoPending`](crate::PingError::IoPending), and the next ones are still sent on time. On Windows, the
+/// requests are sent while the stream is polled. No more requests are sent once the stream is dropped.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use futures::StreamExt;
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// let schedule = ping_rs::Schedule { count: Some(4), ..Default::default() };
+/// let mut replies = ping_rs::ping_stream(&addr, &schedule, Duration::from_secs(2), &[1,2,3,4], None);
+/// futures::executor::block_on(async {
+///     while let Some(reply) = replies.next().await {
+///         println!("{:?}: {:?}", reply.sequence, reply.result);
+///     }
+/// });
+/// ```
+pub fn ping_stream(addr: &IpAddr, schedule: &Schedule, timeout: Duration, data: &[u8], options: Option<&PingOptions>)
+                   -> impl Stream<Item = PeriodicReply> + Send {
+    ping_mod::periodic::PingStream::new(addr, schedule, timeout, data, options)
+}
+
+/// Ping `addr` on `schedule`, blocking. The requests share one socket (one ICMP handle on Windows), and each is sent when
+/// it is due. Unlike [`ping_stream`], a reply is waited for up to `timeout` but no longer than until the next request is
+/// due, so that one is sent on time. Requests that became due while the iterator was not asked for its next result are
+/// skipped, except the last one, which is sent right away. The iteration ends with the schedule, or as soon as it is
+/// stopped by a [`StopHandle`] of the iterator, even while waiting for a reply.
+///
+/// ```rust,no_run
+/// use std::{thread, time::Duration};
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// let replies = ping_rs::ping_iter(&addr, &Default::default(), Duration::from_secs(1), &[1,2,3,4], None).unwrap();
+/// let stop = replies.stop_handle();
+/// thread::spawn(move || { thread::sleep(Duration::from_secs(10)); stop.stop(); });
+/// for result in replies {
+///     println!("{result:?}");
+/// }
+/// ```
+pub fn ping_iter(addr: &IpAddr, schedule: &Schedule, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> Result<PingIter> {
+    Ok(PingIter {
+        pinger: ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), options)?,
+        schedule: *schedule,
+        timeout,
+        data: data.to_vec(),
+        stop: StopHandle::default(),
+        start: None,
+        next: 0,
+    })
+}
+
+/// Iterator of the results of [`ping_iter`], one per request.
+pub struct PingIter {
+    pinger: ping_mod::Pinger,
+    schedule: Schedule,
+    timeout: Duration,
+    data: Vec<u8>,
+    stop: StopHandle,
+    /// When the first request was sent
+    start: Option<Instant>,
+    /// Index of the next request
+    next: usize,
+}
+
+impl PingIter {
+    /// Handle to stop the iteration from another thread.
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+}
+
+impl Iterator for PingIter {
+    type Item = PingApiOutput;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let index = self.schedule.due(start, Instant::now(), self.next);
+        let time = self.schedule.time(start, index)?;
+        if self.stop.wait_until(time) { return None; }
+        self.next = index + 1;
+
+        let wait = match self.schedule.time(start, self.next) {
+            // at least a millisecond, as a zero timeout is invalid
+            Some(next) => self.timeout.min(next.saturating_duration_since(Instant::now()).max(Duration::from_millis(1))),
+            None => self.timeout
+        };
+        let ping = pin!(self.pinger.ping_async(wait, Arc::new(&self.data[..])));
+        match block_on(future::select(ping, pin!(self.stop.stopped()))) {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None
+        }
+    }
+}
+
+/// Stops a [`PingIter`], also while it waits for a reply.
+#[derive(Clone, Default)]
+pub struct StopHandle(Arc<(Mutex<Stop>, Condvar)>);
+
+#[derive(Default)]
+struct Stop {
+    stopped: bool,
+    /// Task waiting for a reply
+    waker: Option<Waker>,
+}
+
+impl StopHandle {
+    /// Stop the iteration: no more requests are sent, and the reply of the last one is no longer waited for.
+    pub fn stop(&self) {
+        let (stop, condvar) = &*self.0;
+        let mut stop = stop.lock().unwrap();
+        stop.stopped = true;
+        condvar.notify_all();
+        if let Some(waker) = stop.waker.take() { waker.wake(); }
+    }
+
+    /// Wait until `time`, or less if stopped. The result is whether it is stopped.
+    fn wait_until(&self, time: Instant) -> bool {
+        let (stop, condvar) = &*self.0;
+        let mut stop = stop.lock().unwrap();
+        while !stop.stopped {
+            let remaining = time.saturating_duration_since(Instant::now());
+            if remaining.is_zero() { break; }
+            stop = condvar.wait_timeout(stop, remaining).unwrap().0;
+        }
+        stop.stopped
+    }
+
+    /// Completes once stopped.
+    fn stopped(&self) -> impl Future<Output = ()> + '_ {
+        future::poll_fn(|cx| {
+            let mut stop = self.0.0.lock().unwrap();
+            if stop.stopped { return Poll::Ready(()); }
+            stop.waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::periodic::{Schedule, StopHandle};
+
+    #[test]
+    fn schedule_stops_at_count_or_deadline() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let times = |schedule: Schedule| (0..).map_while(|i| schedule.time(start, i)).map(|t| t - start).collect::<Vec<_>>();
+        assert_eq!(times(Schedule { count: Some(3), ..Default::default() }), vec![Duration::ZERO, second, second * 2]);
+        assert_eq!(times(Schedule { count: Some(3), deadline: Some(second), ..Default::default() }), vec![Duration::ZERO, second]);
+        assert_eq!(times(Schedule { interval: second / 2, deadline: Some(second), ..Default::default() }).len(), 3);
+    }
+
+    #[test]
+    fn schedule_skips_missed_requests() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let schedule = Schedule { count: Some(5), ..Default::default() };
+        assert_eq!(schedule.due(start, start, 0), 0);
+        assert_eq!(schedule.due(start, start + second / 2, 1), 1);
+        assert_eq!(schedule.due(start, start + second * 3 + second / 2, 1), 3);
+        assert_eq!(schedule.due(start, start + second * 60, 1), 4);
+    }
+
+    #[test]
+    fn stop_handle_ends_wait() {
+        let stop = StopHandle::default();
+        assert!(!stop.wait_until(Instant::now() + Duration::from_millis(10)));
+
+        let start = Instant::now();
+        let handle = stop.clone();
+        thread::spawn(move || { thread::sleep(Duration::from_millis(10)); handle.stop(); });
+        assert!(stop.wait_until(start + Duration::from_secs(60)));
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn stop_handle_wakes_task() {
+        let stop = StopHandle::default();
+        let handle = stop.clone();
+        thread::spawn(move || { thread::sleep(Duration::from_millis(10)); handle.stop(); });
+        futures::executor::block_on(stop.stopped());
+    }
+}
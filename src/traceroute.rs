@@ -0,0 +1,109 @@
+//! Trace the route to an address with TTL-limited echo requests.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::{IpStatus, PingOptions, Result, ping_mod};
+
+/// Options of [`traceroute`].
+#[derive(Debug, Clone)]
+pub struct TraceOptions {
+    /// Largest TTL to probe with
+    pub max_hops: u8,
+    /// Number of probes sent to each hop
+    pub probes: usize,
+    /// Options of the probes. Their `ttl` is replaced by the one of the hop.
+    pub ping: PingOptions,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions { max_hops: 30, probes: 3, ping: PingOptions::default() }
+    }
+}
+
+/// Answer to a probe of a hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopProbe {
+    /// Sender of the answer: a router on the way, or the destination
+    pub address: IpAddr,
+    /// Time from sending the probe to receiving the answer
+    pub rtt: Duration,
+    /// [`IpStatus::TtlExpired`] from a router, [`IpStatus::Success`] from the destination, or another error status
+    pub status: IpStatus,
+}
+
+/// A hop of the route.
+#[derive(Debug, Clone)]
+pub struct Hop {
+    /// TTL of the probes
+    pub ttl: u8,
+    /// Result of each probe, in order. Probes without answer are [`PingError::TimedOut`](crate::PingError::TimedOut).
+    pub probes: Vec<Result<HopProbe>>,
+}
+
+impl Hop {
+    /// Whether the route ends at this hop: some probe reached the destination or was answered by an error other than
+    /// an expired TTL.
+    pub fn is_last(&self) -> bool {
+        self.probes.iter().any(|p| p.as_ref().is_ok_and(|p| p.status != IpStatus::TtlExpired))
+    }
+}
+
+/// Trace the route to `addr`, probing each hop with echo requests of increasing TTL. The trace stops at the first hop that
+/// [is the last](Hop::is_last), or after `max_hops` hops.
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// let addr = "8.8.8.8".parse().unwrap();
+/// for hop in ping_rs::traceroute(&addr, Duration::from_secs(1), &[1,2,3,4], &Default::default()).unwrap() {
+///     let probes: Vec<_> = hop.probes.iter().map(|p| p.as_ref().map(|p| (p.address, p.rtt)).ok()).collect();
+///     println!("{} {:?}", hop.ttl, probes);
+/// }
+/// ```
+pub fn traceroute(addr: &IpAddr, timeout: Duration, data: &[u8], options: &TraceOptions) -> Result<Vec<Hop>> {
+    let mut hops = Vec::new();
+    for ttl in 1..=options.max_hops {
+        let mut pinger = ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), Some(&PingOptions { ttl, ..options.ping.clone() }))?;
+        let hop = Hop { ttl, probes: (0..options.probes).map(|_| pinger.probe(timeout, data)).collect() };
+        let is_last = hop.is_last();
+        hops.push(hop);
+        if is_last { break; }
+    }
+    Ok(hops)
+}
+
+/// Same as [`traceroute`], but asynchronous. See [`send_ping_async`](crate::send_ping_async) for the parameters.
+#[allow(clippy::redundant_allocation)]
+pub async fn traceroute_async(addr: &IpAddr, timeout: Duration, data: Arc<&[u8]>, options: &TraceOptions) -> Result<Vec<Hop>> {
+    let mut hops = Vec::new();
+    for ttl in 1..=options.max_hops {
+        let mut pinger = ping_mod::Pinger::new(&SocketAddr::new(*addr, 0), Some(&PingOptions { ttl, ..options.ping.clone() }))?;
+        let mut hop = Hop { ttl, probes: Vec::with_capacity(options.probes) };
+        for _ in 0..options.probes {
+            hop.probes.push(pinger.probe_async(timeout, data.clone()).await);
+        }
+        let is_last = hop.is_last();
+        hops.push(hop);
+        if is_last { break; }
+    }
+    Ok(hops)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+    use crate::{IpStatus, PingError};
+    use crate::traceroute::{Hop, HopProbe};
+
+    #[test]
+    fn hop_is_last_unless_ttl_expired() {
+        let probe = |status| Ok(HopProbe { address: IpAddr::from(Ipv4Addr::LOCALHOST), rtt: Duration::ZERO, status });
+        let hop = |probes| Hop { ttl: 1, probes };
+        assert!(!hop(vec![Err(PingError::TimedOut), probe(IpStatus::TtlExpired)]).is_last());
+        assert!(hop(vec![Err(PingError::TimedOut), probe(IpStatus::Success)]).is_last());
+        assert!(hop(vec![probe(IpStatus::DestinationHostUnreachable)]).is_last());
+    }
+}
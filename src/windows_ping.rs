@@ -3,68 +3,201 @@
 mod ping_v4;
 mod ping_v6;
 mod ping_future;
+mod timer;
+pub(crate) mod periodic;
 
 use std::ffi::c_void;
-use std::net::IpAddr;
+use std::{io, mem};
+use std::ffi::CString;
+use std::net::{IpAddr, SocketAddr};
 use std::ptr::null_mut;
 use std::sync::Arc;
-use std::time::Duration;
-use windows::core::PSTR;
+use std::time::{Duration, Instant, SystemTime};
 use windows::Win32::Foundation::{ERROR_IO_PENDING, GetLastError, HANDLE};
-use windows::Win32::NetworkManagement::IpHelper::{Icmp6CreateFile, IcmpCloseHandle, IcmpCreateFile, IcmpHandle, IP_OPTION_INFORMATION, IP_STATUS_BASE};
-use windows::Win32::System::Diagnostics::Debug::*;
-use crate::{IpStatus, PingApiOutput, PingError, PingOptions, PingReply, Result};
+use windows::Win32::NetworkManagement::IpHelper::{Icmp6CreateFile, IcmpCloseHandle, IcmpCreateFile, IcmpHandle, if_nametoindex, IP_OPTION_INFORMATION, IP_STATUS_BASE};
+use windows::core::PCSTR;
+use windows::Win32::Networking::WinSock::{getnameinfo, NI_MAXHOST, NI_NAMEREQD, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6, WSACleanup, WSADATA, WSAStartup};
+use crate::{HopProbe, IpStatus, PingApiOutput, PingError, PingOptions, PingReply, Result};
+
+pub(crate) const ICMP_HEADER_SIZE: usize = 8;
+
+/// Largest IP packet.
+pub(crate) const MAX_PACKET_SIZE: usize = 65535;
 
 pub(crate) const MAX_UDP_PACKET: usize = 0xFFFF + 256; // size of ICMP_ECHO_REPLY * 2 + ip header info
 
 /// Send ICMP Echo package (ping) to the given address.
 pub fn send_ping(addr: &IpAddr, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingApiOutput {
-    let _ = validate_data_buffer(data)?;
-    let handle = initialize_icmp_handle(addr)?;
-    let mut reply_buffer: Vec<u8> = vec![0; MAX_UDP_PACKET];
-
-    let reply = echo(handle.icmp(), handle.1, None, data, reply_buffer.as_mut_ptr(), timeout, options)?;
-    handle.icmp().create_raw_reply(reply).into()
+    Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping(timeout, data)
 }
 
 /// Asynchronously schedule ICMP Echo package (ping) to the given address. Note that some parameter signatures are different
 /// from [`send_ping`] function, as the caller should manage those parameters' lifetime.
+#[allow(clippy::redundant_allocation)]
 pub async fn send_ping_async(addr: &IpAddr, timeout: Duration, data: Arc<&[u8]>, options: Option<&PingOptions>) -> PingApiOutput {
-    let validation = validate_data_buffer(data.as_ref());
-    if validation.is_err() {
-        return Err(validation.err().unwrap());
+    Pinger::new(&SocketAddr::new(*addr, 0), options)?.ping_async(timeout, data).await
+}
+
+pub(crate) struct Pinger {
+    handle: PingHandle,
+    options: Option<PingOptions>,
+}
+
+impl Pinger {
+    pub fn new(addr: &SocketAddr, options: Option<&PingOptions>) -> Result<Pinger> {
+        Ok(Pinger { handle: initialize_icmp_handle(addr)?, options: options.cloned() })
+    }
+
+    pub fn ping(&mut self, timeout: Duration, data: &[u8]) -> PingApiOutput {
+        self.echo_raw(timeout, data)?.into()
+    }
+
+    #[allow(clippy::redundant_allocation)]
+    pub async fn ping_async(&mut self, timeout: Duration, data: Arc<&[u8]>) -> PingApiOutput {
+        let _ = validate_data_buffer(data.as_ref())?;
+        ping_future::FutureEchoReplyAsyncState::new(&self.handle, data, timeout, self.options.as_ref()).await?.into()
+    }
+
+    pub fn probe(&mut self, timeout: Duration, data: &[u8]) -> Result<HopProbe> {
+        hop_probe(self.echo_raw(timeout, data)?)
+    }
+
+    #[allow(clippy::redundant_allocation)]
+    pub async fn probe_async(&mut self, timeout: Duration, data: Arc<&[u8]>) -> Result<HopProbe> {
+        let _ = validate_data_buffer(data.as_ref())?;
+        hop_probe(ping_future::FutureEchoReplyAsyncState::new(&self.handle, data, timeout, self.options.as_ref()).await?)
+    }
+
+    /// The API does not report the MTU of "Packet too big" errors.
+    pub fn next_hop_mtu(&self) -> Option<usize> {
+        None
+    }
+
+    /// The reply, whatever its status.
+    fn echo_raw(&mut self, timeout: Duration, data: &[u8]) -> Result<PingRawReply> {
+        let _ = validate_data_buffer(data)?;
+        let mut reply_buffer: Vec<u8> = vec![0; MAX_UDP_PACKET];
+
+        let start_ts = Instant::now();
+        let result = echo(self.handle.icmp(), self.handle.1, None, data, reply_buffer.as_mut_ptr(), timeout, self.options.as_ref());
+        let rtt = start_ts.elapsed();
+        let reply = match result {
+            Ok(reply) => self.handle.icmp().create_raw_reply(reply, data.len()),
+            // the failing status may come with the reply of the error's sender
+            Err(PingError::IpError(status)) => {
+                let reply = self.handle.icmp().create_raw_reply(reply_buffer.as_mut_ptr(), data.len());
+                if reply.status != status as u32 { return Err(PingError::IpError(status)); }
+                reply
+            },
+            Err(e) => return Err(e)
+        };
+        Ok(reply.with_local_rtt(rtt))
     }
-    let handle = initialize_icmp_handle(addr).unwrap();
-    ping_future::FutureEchoReplyAsyncState::new(handle, data, timeout, options).await
+}
+
+/// Index of the network interface `name`.
+pub(crate) fn interface_index(name: &str) -> io::Result<u32> {
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    match unsafe { if_nametoindex(PCSTR::from_raw(name.as_ptr() as *const u8)) } {
+        0 => Err(io::Error::from(io::ErrorKind::NotFound)),
+        index => Ok(index)
+    }
+}
+
+/// Name of `addr` by a reverse lookup.
+pub(crate) fn lookup_host_name(addr: &IpAddr) -> io::Result<String> {
+    let mut name = [0u8; NI_MAXHOST as usize];
+    let result = unsafe {
+        let mut wsa_data = WSADATA::default();
+        WSAStartup(0x202, &mut wsa_data);
+        let result = match SocketAddr::new(*addr, 0) {
+            SocketAddr::V4(a) => {
+                let a = SOCKADDR_IN::from(a);
+                getnameinfo(&a as *const _ as *const SOCKADDR, mem::size_of_val(&a) as i32, Some(&mut name), None, NI_NAMEREQD as i32)
+            },
+            SocketAddr::V6(a) => {
+                let a = SOCKADDR_IN6::from(a);
+                getnameinfo(&a as *const _ as *const SOCKADDR, mem::size_of_val(&a) as i32, Some(&mut name), None, NI_NAMEREQD as i32)
+            }
+        };
+        WSACleanup();
+        result
+    };
+    if result != 0 { return Err(io::Error::from_raw_os_error(result)); }
+    let size = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    Ok(String::from_utf8_lossy(&name[..size]).into_owned())
 }
 
 pub(crate) type ReplyBuffer = [u8; MAX_UDP_PACKET];
 
 pub(crate) struct PingRawReply {
     pub address: IpAddr,
+    pub scope_id: Option<u32>,
     pub status: u32,
-    pub rtt: u32
+    pub rtt: Duration,
+    pub ttl: Option<u8>,
+    pub tos: Option<u8>,
+    pub data: Vec<u8>,
+    pub received_at: SystemTime,
+}
+
+impl PingRawReply {
+    /// The reply with `rtt` measured locally when it is successful, since the API only measures whole milliseconds. Errors
+    /// keep the time reported by the API.
+    pub(crate) fn with_local_rtt(self, rtt: Duration) -> Self {
+        if self.status == IpStatus::Success as u32 { PingRawReply { rtt, ..self } } else { self }
+    }
 }
 
 impl Into<PingApiOutput> for PingRawReply {
     fn into(self) -> PingApiOutput {
-        parse_raw_reply_status(self.status).map(|_| PingReply { address: self.address, rtt: self.rtt })
+        parse_raw_reply_status(self.status).map(|_| PingReply {
+            address: self.address,
+            scope_id: self.scope_id,
+            source: None,
+            rtt: self.rtt,
+            ttl: self.ttl,
+            tos: self.tos,
+            sequence: None,
+            ident: None,
+            size: ICMP_HEADER_SIZE + self.data.len(),
+            data: self.data,
+            sent_at: self.received_at - self.rtt,
+            received_at: self.received_at,
+        })
     }
 }
 
 pub(crate) trait IcmpEcho {
-    fn send(&self, handle: IcmpHandle, event: Option<HANDLE>, data: *const c_void, data_len: u16, options: *const IP_OPTION_INFORMATION,
-            reply_buffer: *mut c_void, reply_buffer_len: u32, timeout: u32) -> u32;
-    fn create_raw_reply(&self, reply: *mut u8) -> PingRawReply;
+    /// The source of `request` is of the same family as `self`.
+    fn send(&self, request: &EchoRequest) -> u32;
+    /// `data_size` is the size of the request data, for replies that do not tell theirs.
+    fn create_raw_reply(&self, reply: *mut u8, data_size: usize) -> PingRawReply;
+    fn is_ipv4(&self) -> bool;
+}
+
+/// Arguments of [`IcmpEcho::send`], as the ICMP API takes them.
+pub(crate) struct EchoRequest {
+    pub handle: IcmpHandle,
+    /// Event signaled on the reply, for an asynchronous request
+    pub event: Option<HANDLE>,
+    pub source: Option<IpAddr>,
+    pub data: *const c_void,
+    pub data_len: u16,
+    pub options: *const IP_OPTION_INFORMATION,
+    pub reply_buffer: *mut c_void,
+    pub reply_buffer_len: u32,
+    /// In milliseconds
+    pub timeout: u32,
 }
 
-pub(crate) struct PingHandle<'a>(pub &'a IpAddr, IcmpHandle);
+pub(crate) struct PingHandle(pub SocketAddr, IcmpHandle);
 
-impl<'a> PingHandle<'a> {
-    pub(crate) fn icmp(&self) -> &'a dyn IcmpEcho {
+impl PingHandle {
+    pub(crate) fn icmp(&self) -> &dyn IcmpEcho {
         match &self.0 {
-            IpAddr::V4(ip) => ip,
-            IpAddr::V6(ip) => ip,
+            SocketAddr::V4(addr) => addr.ip(),
+            SocketAddr::V6(addr) => addr,
         }
     }
 
@@ -73,7 +206,7 @@ impl<'a> PingHandle<'a> {
     }
 }
 
-impl<'a> Drop for PingHandle<'a> {
+impl Drop for PingHandle {
     fn drop(&mut self) {
         let result = unsafe { IcmpCloseHandle(self.1) };
         assert!(result.as_bool());
@@ -86,31 +219,39 @@ fn validate_data_buffer(data: &[u8]) -> Result<&[u8]> {
     if data.len() > MAX_BUFFER_SIZE { Err(PingError::DataSizeTooBig(MAX_BUFFER_SIZE)) } else { Ok(data) }
 }
 
-fn initialize_icmp_handle(addr: &IpAddr) -> Result<PingHandle> {
+fn initialize_icmp_handle(addr: &SocketAddr) -> Result<PingHandle> {
     unsafe {
         let handle = match addr {
-            IpAddr::V4(_) => IcmpCreateFile().map(|h| PingHandle(addr, h)),
-            IpAddr::V6(_) => Icmp6CreateFile().map(|h| PingHandle(addr, h))
+            SocketAddr::V4(_) => IcmpCreateFile().map(|h| PingHandle(*addr, h)),
+            SocketAddr::V6(_) => Icmp6CreateFile().map(|h| PingHandle(*addr, h))
         };
-        handle.map_err(|e| e.code().0 as u32).map_err(ping_reply_error)
+        handle.map_err(|e| PingError::OsError(Arc::new(e.into())))
     }
 }
 
 const DONT_FRAGMENT_FLAG: u8 = 2;
 pub(crate) fn echo(destination: &dyn IcmpEcho, handle: IcmpHandle, event: Option<HANDLE>, buffer: &[u8], reply_buffer: *mut u8, timeout: Duration,
                       options: Option<&PingOptions>) -> Result<*mut u8> {
-    let request_data = buffer.as_ptr() as *const c_void;
+    let source = options.and_then(|v| v.source);
+    if source.is_some_and(|s| s.is_ipv4() != destination.is_ipv4()) { return Err(PingError::BadParameter("source")); }
     let ip_options = IP_OPTION_INFORMATION {
         Ttl: options.clone().map(|v| v.ttl).unwrap_or(128),
-        Tos: 0,
+        Tos: options.map_or(0, |v| v.tos),
         Flags: options.and_then(|v| if v.dont_fragment { Some(DONT_FRAGMENT_FLAG) } else { None } ).unwrap_or(0),
         OptionsSize: 0,
         OptionsData: null_mut()
     };
-    let ip_options_ptr = &ip_options as *const IP_OPTION_INFORMATION;
+    let request = EchoRequest {
+        handle, event, source,
+        data: buffer.as_ptr() as *const c_void,
+        data_len: buffer.len() as u16,
+        options: &ip_options,
+        reply_buffer: reply_buffer as *mut c_void,
+        reply_buffer_len: MAX_UDP_PACKET as u32,
+        timeout: timeout.as_millis() as u32,
+    };
 
-    let error = destination.send(handle, event, request_data, buffer.len() as u16, ip_options_ptr,
-                reply_buffer as *mut c_void, MAX_UDP_PACKET as u32, timeout.as_millis() as u32);
+    let error = destination.send(&request);
     if error == 0 {
         let win_err = unsafe { GetLastError() };
         if win_err == ERROR_IO_PENDING { Err(PingError::IoPending) } else { Err(ping_reply_error(win_err.0)) }
@@ -120,31 +261,27 @@ pub(crate) fn echo(destination: &dyn IcmpEcho, handle: IcmpHandle, event: Option
     }
 }
 
+/// Traceroute probe of `reply`, which is from the destination or the sender of an ICMP error.
+fn hop_probe(reply: PingRawReply) -> Result<HopProbe> {
+    let status = match parse_raw_reply_status(reply.status) {
+        Ok(()) => IpStatus::Success,
+        Err(PingError::IpError(status)) => status,
+        Err(e) => return Err(e)
+    };
+    Ok(HopProbe { address: reply.address, rtt: reply.rtt, status })
+}
+
 fn parse_raw_reply_status(status: u32) -> Result<()> {
-    if status as IpStatus::Type == IpStatus::Success { Ok(()) }
-    else {
-        match ping_reply_error(status) {
-            v @ PingError::OsError(_, _) => return Err(v),
-            PingError::IpError(v) => Err(ping_reply_error(v)),
-            PingError::TimedOut => Err(PingError::TimedOut),
-            _ => panic!("Dev bug!")
-        }
-    }
+    if status == IpStatus::Success as u32 { Ok(()) } else { Err(ping_reply_error(status)) }
 }
 
 fn ping_reply_error(status_code: u32) -> PingError {
-    if status_code < IP_STATUS_BASE {
-        let mut buffer = [0u8; 32];
-        let s = PSTR::from_raw(buffer.as_mut_ptr());
-        let r = unsafe { FormatMessageA(FORMAT_MESSAGE_FROM_SYSTEM, None, status_code, 0, s, buffer.len() as u32, None) };
-        PingError::OsError(status_code, if r == 0 {
-            format!("Ping failed ({status_code})")
-        } else {
-            unsafe { s.to_string().unwrap() }
-        })
-    } else {
-        if status_code == IpStatus::TimedOut { PingError::TimedOut }
-        else { PingError::IpError(status_code) }
+    if status_code < IP_STATUS_BASE { PingError::OsError(Arc::new(io::Error::from_raw_os_error(status_code as i32))) }
+    else {
+        match IpStatus::try_from(status_code) {
+            Ok(IpStatus::TimedOut) => PingError::TimedOut,
+            Ok(status) => PingError::IpError(status),
+            Err(_) => PingError::IpError(IpStatus::GeneralFailure)
+        }
     }
 }
-
@@ -0,0 +1,94 @@
+use std::future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use futures::{FutureExt, Stream, StreamExt, stream};
+use futures::channel::mpsc;
+use futures::stream::FuturesOrdered;
+use crate::{PeriodicReply, PingOptions, Result, Schedule};
+use crate::windows_ping::{PingRawReply, Pinger, validate_data_buffer};
+use crate::windows_ping::ping_future::FutureEchoReplyAsyncState;
+use crate::windows_ping::timer::Timer;
+
+/// Replies of requests sent on a schedule through one ICMP handle. The requests are sent while the stream is polled, and
+/// stop once it is dropped. The sequence numbers of the requests are not known, and late replies are dropped.
+pub(crate) struct PingStream(Pin<Box<dyn Stream<Item = PeriodicReply> + Send>>);
+
+impl PingStream {
+    pub(crate) fn new(addr: &IpAddr, schedule: &Schedule, timeout: Duration, data: &[u8], options: Option<&PingOptions>) -> PingStream {
+        let (sender, receiver) = mpsc::unbounded();
+        let requests = send_requests(SocketAddr::new(*addr, 0), *schedule, timeout, data.to_vec(), options.cloned(), sender);
+        PingStream(Box::pin(stream::select(requests.into_stream().filter_map(|()| future::ready(None)), receiver)))
+    }
+}
+
+impl Stream for PingStream {
+    type Item = PeriodicReply;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_next_unpin(cx)
+    }
+}
+
+// INTERNAL
+
+enum Event {
+    /// The next request is due
+    Tick(std::io::Result<()>),
+    Reply(Result<PingRawReply>),
+    /// The schedule is over and every request answered or timed out
+    Done,
+}
+
+/// Send the requests of `schedule` when they are due, and pass their replies to `sender` in order.
+async fn send_requests(addr: SocketAddr, schedule: Schedule, timeout: Duration, data: Vec<u8>, options: Option<PingOptions>,
+                       sender: mpsc::UnboundedSender<PeriodicReply>) {
+    let send = |result| sender.unbounded_send(PeriodicReply { sequence: None, result, late: false });
+    let pinger = match Pinger::new(&addr, options.as_ref()).and_then(|pinger| validate_data_buffer(&data).map(|_| pinger)) {
+        Ok(pinger) => pinger,
+        Err(e) => {
+            let _ = send(Err(e));
+            return;
+        }
+    };
+    let start = Instant::now();
+    let mut index = 0;
+    let mut tick = schedule.time(start, index).map(Timer::new);
+    let mut requests = FuturesOrdered::new();
+    // why the requests stopped before the end of the schedule
+    let mut error = None;
+    loop {
+        let event = future::poll_fn(|cx| {
+            if let Some(Poll::Ready(result)) = tick.as_mut().map(|timer| timer.poll_unpin(cx)) {
+                return Poll::Ready(Event::Tick(result));
+            }
+            match requests.poll_next_unpin(cx) {
+                Poll::Ready(Some(reply)) => Poll::Ready(Event::Reply(reply)),
+                Poll::Ready(None) if tick.is_none() => Poll::Ready(Event::Done),
+                _ => Poll::Pending
+            }
+        }).await;
+
+        match event {
+            Event::Tick(Ok(())) => {
+                requests.push_back(FutureEchoReplyAsyncState::new(&pinger.handle, Arc::new(&data[..]), timeout, pinger.options.as_ref()));
+                index += 1;
+                tick = schedule.time(start, index).map(Timer::new);
+            },
+            // no more requests can be sent
+            Event::Tick(Err(e)) => {
+                error = Some(e.into());
+                tick = None;
+            },
+            Event::Reply(reply) => {
+                if send(reply.and_then(Into::into)).is_err() { return; }
+            },
+            Event::Done => {
+                if let Some(e) = error { let _ = send(Err(e)); }
+                return;
+            }
+        }
+    }
+}
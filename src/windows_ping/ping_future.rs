@@ -1,20 +1,22 @@
 use std::ffi::c_void;
+use std::io;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc};
+use std::sync::{Arc, OnceLock};
 use std::task::{Context, Poll, Waker};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use windows::Win32::Foundation::{BOOLEAN, CloseHandle, GetLastError, HANDLE, WAIT_TIMEOUT, WAIT_OBJECT_0, WAIT_FAILED};
 use windows::Win32::System::Threading::{CreateEventA, RegisterWaitForSingleObject, UnregisterWait, WaitForSingleObject, WT_EXECUTEONLYONCE};
 use windows::Win32::System::WindowsProgramming::INFINITE;
-use crate::{windows_ping, PingApiOutput, PingError, PingOptions};
-use crate::windows_ping::{MAX_UDP_PACKET, PingHandle};
+use crate::{windows_ping, PingError, PingOptions, Result};
+use crate::windows_ping::{MAX_UDP_PACKET, PingHandle, PingRawReply};
 
-pub struct FutureEchoReplyAsyncState<'a> {
-    handle: PingHandle<'a>,
+pub(crate) struct FutureEchoReplyAsyncState<'a> {
+    handle: &'a PingHandle,
     data: Arc<&'a [u8]>,
     timeout: Duration,
     options: Option<&'a PingOptions>,
+    start_ts: Instant,
 
     ping_event: HANDLE,
     event_registration: HANDLE,
@@ -22,44 +24,54 @@ pub struct FutureEchoReplyAsyncState<'a> {
     /// A fixed address for ICMP reply
     reply_buffer: Pin<Arc<windows_ping::ReplyBuffer>>,
 
-    waker: Pin<Arc<Option<Waker>>>,
+    signal: Pin<Arc<EventSignal>>,
+}
+
+/// What the callback of the ping event shares with the future.
+#[derive(Default)]
+struct EventSignal {
+    waker: Option<Waker>,
+    /// When the event fired, that is when the reply arrived
+    fired_at: OnceLock<Instant>,
 }
 
 unsafe extern "system" fn reply_callback(data: *mut c_void, _is_timeout: BOOLEAN){
-    let waker = &*(data as *const Option<Waker>);
+    let signal = &*(data as *const EventSignal);
+    let _ = signal.fired_at.set(Instant::now());
 
-    if waker.is_some() {
-        waker.clone().unwrap().wake();
+    if signal.waker.is_some() {
+        signal.waker.clone().unwrap().wake();
     }
 }
 
-fn register_event(waker_address: *const c_void) -> (HANDLE, HANDLE) {
+fn register_event(signal_address: *const c_void) -> (HANDLE, HANDLE) {
     let ping_event = unsafe { CreateEventA(None, true, false, None).unwrap() };
     let mut event_registration = HANDLE::default();
 
     unsafe {
-        let result = RegisterWaitForSingleObject(&mut event_registration, ping_event, Some(reply_callback), Some(waker_address), INFINITE, WT_EXECUTEONLYONCE);
+        let result = RegisterWaitForSingleObject(&mut event_registration, ping_event, Some(reply_callback), Some(signal_address), INFINITE, WT_EXECUTEONLYONCE);
         assert!(result.as_bool());
     }
     (ping_event, event_registration)
 }
 
 impl<'a> FutureEchoReplyAsyncState<'a> {
-    pub(crate) fn new(handle: PingHandle<'a>, data: Arc<&'a [u8]>, timeout: Duration, options: Option<&'a PingOptions>) -> Self {
+    pub(crate) fn new(handle: &'a PingHandle, data: Arc<&'a [u8]>, timeout: Duration, options: Option<&'a PingOptions>) -> Self {
         Self {
             handle,
             data,
             timeout,
             options,
+            start_ts: Instant::now(),
             ping_event: Default::default(),
             event_registration: Default::default(),
             reply_buffer: Arc::pin([0; MAX_UDP_PACKET]),
-            waker: Arc::pin(None)
+            signal: Arc::pin(EventSignal::default())
         }
     }
 
-    fn waker_address(&self) -> *mut Option<Waker> {
-        Arc::into_raw(Pin::into_inner(self.waker.clone())) as *mut Option<Waker>
+    fn signal_address(&self) -> *mut EventSignal {
+        Arc::into_raw(Pin::into_inner(self.signal.clone())) as *mut EventSignal
     }
 
     /// [`reply_buffer`] is a fixed address, so a mutable reference shouldn't be an issue.
@@ -67,21 +79,23 @@ impl<'a> FutureEchoReplyAsyncState<'a> {
         Arc::into_raw(Pin::into_inner(self.reply_buffer.clone())) as *mut u8
     }
 
-    fn start(&mut self) -> Option<Poll<PingApiOutput>> {
-        (self.ping_event, self.event_registration) = register_event(self.waker_address() as *const c_void);
+    fn start(&mut self) -> Option<Poll<Result<PingRawReply>>> {
+        (self.ping_event, self.event_registration) = register_event(self.signal_address() as *const c_void);
+        self.start_ts = Instant::now();
 
         let raw_reply = windows_ping::echo(self.handle.icmp(), *self.handle.icmp_handle(), Some(self.ping_event), self.data.as_ref(),
                                            self.mut_reply_buffer(), self.timeout, self.options)
-            .map(|reply| self.handle.icmp().create_raw_reply(reply));
+            .map(|reply| self.handle.icmp().create_raw_reply(reply, self.data.len()).with_local_rtt(self.start_ts.elapsed()));
         match raw_reply {
             Err(PingError::IoPending) => None,
-            result => Some(Poll::Ready(result.and_then(|x| x.into())))
+            result => Some(Poll::Ready(result))
         }
     }
 }
 
 impl<'a> Future for FutureEchoReplyAsyncState<'a> {
-    type Output = PingApiOutput;
+    /// The reply, whatever its status.
+    type Output = Result<PingRawReply>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let async_state = self.get_mut();
@@ -95,13 +109,18 @@ impl<'a> Future for FutureEchoReplyAsyncState<'a> {
 
         match ping_state {
             WAIT_TIMEOUT => unsafe {
-                let addr = async_state.waker_address();
-                *addr = Some(cx.waker().clone());
+                let addr = async_state.signal_address();
+                (*addr).waker = Some(cx.waker().clone());
                 Poll::Pending
             },
-            WAIT_OBJECT_0 => Poll::Ready(async_state.handle.icmp().create_raw_reply(async_state.mut_reply_buffer()).into()),
-            WAIT_FAILED => Poll::Ready(Err(PingError::OsError(unsafe { GetLastError().0 }, "Wait event failed".to_string()))),
-            _ => Poll::Ready(Err(PingError::OsError(ping_state.0, "Unexpected return code!".to_string())))
+            WAIT_OBJECT_0 => {
+                // the callback may not have run yet when the future is polled for another reason
+                let fired_at = async_state.signal.fired_at.get().copied().unwrap_or_else(Instant::now);
+                let reply = async_state.handle.icmp().create_raw_reply(async_state.mut_reply_buffer(), async_state.data.len());
+                Poll::Ready(Ok(reply.with_local_rtt(fired_at - async_state.start_ts)))
+            },
+            WAIT_FAILED => Poll::Ready(Err(io::Error::from_raw_os_error(unsafe { GetLastError().0 } as i32).into())),
+            _ => Poll::Ready(Err(io::Error::other(format!("Unexpected wait result {}", ping_state.0)).into()))
         }
     }
 }
@@ -1,23 +1,37 @@
-use std::ffi::c_void;
 use std::net::{IpAddr, Ipv4Addr};
-use windows::Win32::Foundation::{HANDLE};
-use windows::Win32::NetworkManagement::IpHelper::{ICMP_ECHO_REPLY, IcmpHandle, IcmpSendEcho2, IP_OPTION_INFORMATION};
-use crate::windows_ping::{IcmpEcho, PingRawReply};
+use std::slice;
+use std::time::{Duration, SystemTime};
+use windows::Win32::NetworkManagement::IpHelper::{ICMP_ECHO_REPLY, IcmpSendEcho2, IcmpSendEcho2Ex};
+use crate::windows_ping::{EchoRequest, IcmpEcho, PingRawReply};
 
 impl IcmpEcho for Ipv4Addr {
-    fn send(&self, handle: IcmpHandle, event: Option<HANDLE>, data: *const c_void, data_len: u16, options: *const IP_OPTION_INFORMATION, reply_buffer: *mut c_void, reply_buffer_len: u32, timeout: u32) -> u32 {
+    fn send(&self, request: &EchoRequest) -> u32 {
+        let EchoRequest { handle, event, data, data_len, options, reply_buffer, reply_buffer_len, timeout, .. } = *request;
         unsafe {
             let destination_address = *((&self.octets() as *const u8) as *const u32);
-            IcmpSendEcho2(handle, event, None, None, destination_address, data, data_len as u16, Some(options), reply_buffer, reply_buffer_len, timeout)
+            match request.source {
+                Some(IpAddr::V4(source)) => {
+                    let source_address = *((&source.octets() as *const u8) as *const u32);
+                    IcmpSendEcho2Ex(handle, event, None, None, source_address, destination_address, data, data_len, Some(options),
+                                    reply_buffer, reply_buffer_len, timeout)
+                },
+                _ => IcmpSendEcho2(handle, event, None, None, destination_address, data, data_len, Some(options), reply_buffer, reply_buffer_len, timeout)
+            }
         }
     }
-    fn create_raw_reply(&self, reply: *mut u8) -> PingRawReply {
+    fn create_raw_reply(&self, reply: *mut u8, _data_size: usize) -> PingRawReply {
         let reply = unsafe { *(reply as *const ICMP_ECHO_REPLY) };
 
         // properly handle Network BE
         let addr_ptr = &reply.Address as *const u32 as *const [u8;4];
         let addr = u32::from_be_bytes(unsafe { *addr_ptr });
 
-        PingRawReply { address: IpAddr::V4(Ipv4Addr::from(addr)), status: reply.Status, rtt: reply.RoundTripTime, }
+        let data = if reply.DataSize == 0 { Vec::new() }
+                   else { unsafe { slice::from_raw_parts(reply.Data as *const u8, reply.DataSize as usize) }.to_vec() };
+
+        PingRawReply { address: IpAddr::V4(Ipv4Addr::from(addr)), scope_id: None, status: reply.Status, rtt: Duration::from_millis(reply.RoundTripTime as u64),
+            ttl: Some(reply.Options.Ttl), tos: Some(reply.Options.Tos), data, received_at: SystemTime::now() }
     }
+
+    fn is_ipv4(&self) -> bool { true }
 }
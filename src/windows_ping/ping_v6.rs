@@ -1,22 +1,28 @@
-use std::ffi::c_void;
 use std::net::{IpAddr, Ipv6Addr, SocketAddrV6};
-use windows::Win32::Foundation::HANDLE;
+use std::{mem, slice};
+use std::time::{Duration, SystemTime};
 use windows::Win32::Networking::WinSock::SOCKADDR_IN6;
-use windows::Win32::NetworkManagement::IpHelper::{Icmp6SendEcho2, IcmpHandle, ICMPV6_ECHO_REPLY_LH, IP_OPTION_INFORMATION};
-use crate::windows_ping::{IcmpEcho, PingRawReply};
+use windows::Win32::NetworkManagement::IpHelper::{Icmp6SendEcho2, ICMPV6_ECHO_REPLY_LH};
+use crate::windows_ping::{EchoRequest, IcmpEcho, PingRawReply};
 
-impl IcmpEcho for Ipv6Addr {
-    fn send(&self, handle: IcmpHandle, event: Option<HANDLE>, data: *const c_void, data_len: u16, options: *const IP_OPTION_INFORMATION, reply_buffer: *mut c_void, reply_buffer_len: u32, timeout: u32) -> u32 {
-        let source = SOCKADDR_IN6::default();
-        let destination_address = SOCKADDR_IN6::from(SocketAddrV6::new(self.clone().to_owned(), 0, 0, 0));
+impl IcmpEcho for SocketAddrV6 {
+    fn send(&self, request: &EchoRequest) -> u32 {
+        let EchoRequest { handle, event, data, data_len, options, reply_buffer, reply_buffer_len, timeout, .. } = *request;
+        let source = match request.source {
+            Some(IpAddr::V6(source)) => SOCKADDR_IN6::from(SocketAddrV6::new(source, 0, 0, 0)),
+            _ => SOCKADDR_IN6::default()
+        };
+        let destination_address = SOCKADDR_IN6::from(SocketAddrV6::new(*self.ip(), 0, 0, self.scope_id()));
 
         unsafe {
-            Icmp6SendEcho2(handle, event, None, None, &source, &destination_address, data, data_len as u16, Some(options),
+            Icmp6SendEcho2(handle, event, None, None, &source, &destination_address, data, data_len, Some(options),
                            reply_buffer, reply_buffer_len, timeout)
         }
     }
 
-    fn create_raw_reply(&self, reply: *mut u8) -> PingRawReply {
+    fn create_raw_reply(&self, reply: *mut u8, data_size: usize) -> PingRawReply {
+        // the echoed data follows the reply
+        let data = unsafe { slice::from_raw_parts(reply.add(mem::size_of::<ICMPV6_ECHO_REPLY_LH>()), data_size) }.to_vec();
         let reply = unsafe { *(reply as *const ICMPV6_ECHO_REPLY_LH) };
 
         // correct byte order..
@@ -25,6 +31,10 @@ impl IcmpEcho for Ipv6Addr {
             addr[i] = reply.Address.sin6_addr[i].swap_bytes();
         }
 
-        PingRawReply { address: IpAddr::V6(Ipv6Addr::from(addr)), status: reply.Status, rtt: reply.RoundTripTime, }
+        let scope_id = Some(reply.Address.sin6_scope_id).filter(|&s| s != 0);
+        PingRawReply { address: IpAddr::V6(Ipv6Addr::from(addr)), scope_id, status: reply.Status, rtt: Duration::from_millis(reply.RoundTripTime as u64),
+            ttl: None, tos: None, data, received_at: SystemTime::now() }
     }
+
+    fn is_ipv4(&self) -> bool { false }
 }
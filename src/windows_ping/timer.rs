@@ -0,0 +1,88 @@
+use std::ffi::c_void;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+use windows::Win32::Foundation::{BOOLEAN, CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::System::Threading::{CreateEventA, RegisterWaitForSingleObject, UnregisterWaitEx, WT_EXECUTEONLYONCE};
+
+/// Future that completes at a given time. A wait of the thread pool on an event that is never signaled wakes it.
+pub(crate) struct Timer {
+    at: Instant,
+    event: HANDLE,
+    registration: HANDLE,
+    wait: Arc<Wait>,
+}
+
+/// What the callback of the wait shares with the timer.
+#[derive(Default)]
+struct Wait {
+    waker: Mutex<Option<Waker>>,
+    /// Whether the wait timed out, which may be a bit before the time of the timer
+    done: AtomicBool,
+}
+
+unsafe extern "system" fn wait_callback(data: *mut c_void, _is_timeout: BOOLEAN) {
+    let wait = &*(data as *const Wait);
+    wait.done.store(true, Ordering::Release);
+    if let Some(waker) = wait.waker.lock().unwrap().take() {
+        waker.wake();
+    }
+}
+
+impl Timer {
+    pub(crate) fn new(at: Instant) -> Timer {
+        Timer { at, event: HANDLE::default(), registration: HANDLE::default(), wait: Arc::default() }
+    }
+
+    /// Start a wait of the thread pool until the time of the timer.
+    fn register(&mut self) -> io::Result<()> {
+        self.unregister();
+        if self.event.is_invalid() {
+            self.event = unsafe { CreateEventA(None, true, false, None) }.map_err(io::Error::from)?;
+        }
+        // rounded up, so the wait does not end before `at`
+        let millis = self.at.saturating_duration_since(Instant::now()).as_micros().div_ceil(1000).min(u32::MAX as u128 - 1) as u32;
+        self.wait.done.store(false, Ordering::Release);
+        let result = unsafe {
+            RegisterWaitForSingleObject(&mut self.registration, self.event, Some(wait_callback), Some(Arc::as_ptr(&self.wait) as *const c_void),
+                                        millis, WT_EXECUTEONLYONCE)
+        };
+        if result.as_bool() { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    /// Cancel the wait, waiting for its callback if it is running.
+    fn unregister(&mut self) {
+        if !self.registration.is_invalid() {
+            unsafe { UnregisterWaitEx(self.registration, INVALID_HANDLE_VALUE); }
+            self.registration = HANDLE::default();
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let timer = self.get_mut();
+        if Instant::now() >= timer.at { return Poll::Ready(Ok(())); }
+
+        *timer.wait.waker.lock().unwrap() = Some(cx.waker().clone());
+        if timer.registration.is_invalid() || timer.wait.done.load(Ordering::Acquire) {
+            timer.register()?;
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.unregister();
+        if !self.event.is_invalid() {
+            unsafe { CloseHandle(self.event); }
+        }
+    }
+}